@@ -0,0 +1,86 @@
+/// Collision guard: blocks driving forward while the ultrasonic sensor sees something too close.
+///
+/// Feed it the sensor's readings with [`update`](Self::update) and pass every drive command
+/// through [`filter`](Self::filter). Reversing and turning in place are still allowed, so the
+/// robot can always get away from the obstacle. The default one is disabled.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CollisionGuard {
+    pub enabled: bool,
+    /// in meters, like the sensor's readings
+    pub threshold: f64,
+    distance: Option<f64>,
+}
+
+impl CollisionGuard {
+    pub fn new(threshold: f64) -> Self {
+        Self {
+            enabled: true,
+            threshold,
+            distance: None,
+        }
+    }
+
+    /// A new distance reading, returns `true` if it changed whether the guard is blocking
+    pub fn update(&mut self, distance: f64) -> bool {
+        let was = self.blocking();
+        self.distance = Some(distance);
+        was != self.blocking()
+    }
+
+    /// The last reading, if there's been one
+    pub fn distance(&self) -> Option<f64> {
+        self.distance
+    }
+
+    /// Whether forward motion is being blocked right now
+    pub fn blocking(&self) -> bool {
+        self.enabled && self.distance.is_some_and(|d| d < self.threshold)
+    }
+
+    /// Drop the forward part of a drive command while blocking, keeping the turn
+    pub fn filter(&self, left: f64, right: f64) -> (f64, f64) {
+        let forward = (left + right) / 2.;
+        if !self.blocking() || forward <= 0. {
+            return (left, right);
+        }
+        let turn = (left - right) / 2.;
+        (turn, -turn)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn passes_when_clear() {
+        let mut g = CollisionGuard::new(0.3);
+        assert_eq!(g.filter(1., 1.), (1., 1.));
+        assert!(!g.update(1.));
+        assert_eq!(g.filter(1., 0.5), (1., 0.5));
+    }
+
+    #[test]
+    fn blocks_forward_only() {
+        let mut g = CollisionGuard::new(0.3);
+        assert!(g.update(0.25));
+        assert!(g.blocking());
+        assert_eq!(g.filter(1., 1.), (0., 0.));
+        assert_eq!(g.filter(1., 0.5), (0.25, -0.25));
+        // reverse and spinning in place
+        assert_eq!(g.filter(-1., -1.), (-1., -1.));
+        assert_eq!(g.filter(-0.5, 0.5), (-0.5, 0.5));
+
+        assert!(!g.update(0.2));
+        assert!(g.update(0.5));
+        assert_eq!(g.filter(1., 1.), (1., 1.));
+    }
+
+    #[test]
+    fn disabled() {
+        let mut g = CollisionGuard::new(0.3);
+        g.enabled = false;
+        assert!(!g.update(0.1));
+        assert_eq!(g.filter(1., 1.), (1., 1.));
+    }
+}
@@ -0,0 +1,14 @@
+//! Differential drive helpers shared by the roblib clients in this repo.
+
+mod guard;
+mod mixer;
+mod odometry;
+mod pose;
+pub mod roland;
+pub mod watchdog;
+
+pub use guard::CollisionGuard;
+pub use mixer::{DriveMixer, Mode};
+pub use odometry::{Calibration, Odometry};
+pub use pose::{wrap_angle, Pose};
+pub use watchdog::Watchdog;
@@ -0,0 +1,211 @@
+/// How [`DriveMixer::mix`] interprets its two inputs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Mode {
+    /// `x` is the left wheel, `y` is the right wheel
+    #[default]
+    Tank,
+    /// `y` is the throttle, `x` is the turn rate, spins in place without throttle
+    Arcade,
+    /// `y` is the throttle, `x` bends the path, turning scales with the throttle
+    Curvature,
+}
+
+/// Turns driver inputs into left and right wheel speeds in the `-1..=1` range.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DriveMixer {
+    pub mode: Mode,
+    /// inner wheel speed relative to the outer one when driving diagonally with WASD
+    pub turn_ratio: f64,
+    /// analog inputs with a smaller magnitude than this are treated as zero
+    pub deadzone: f64,
+    /// multiplier applied to both wheels before clamping
+    pub scale: f64,
+}
+
+impl Default for DriveMixer {
+    fn default() -> Self {
+        Self::new(Mode::default())
+    }
+}
+
+impl DriveMixer {
+    pub const fn new(mode: Mode) -> Self {
+        Self {
+            mode,
+            turn_ratio: 1. / 3.,
+            deadzone: 0.,
+            scale: 1.,
+        }
+    }
+
+    /// Mix the WASD keys at the given speed (`0..=1`), `None` means stop.
+    ///
+    /// Opposing keys cancel out, A or D alone spins in place and a diagonal slows the inner
+    /// wheel down by `turn_ratio`.
+    pub fn wasd(&self, [w, a, s, d]: [bool; 4], speed: f64) -> Option<(f64, f64)> {
+        // move in place or don't move at all
+        if w == s {
+            return match (a, d) {
+                (true, false) => Some(self.output(-speed, speed)),
+                (false, true) => Some(self.output(speed, -speed)),
+                _ => None,
+            };
+        }
+
+        let speed = if w { speed } else { -speed };
+
+        // drive straight
+        if a == d {
+            return Some(self.output(speed, speed));
+        }
+
+        // diagonal drive
+        let turn_speed = speed * self.turn_ratio;
+        if a {
+            Some(self.output(turn_speed, speed))
+        } else {
+            Some(self.output(speed, turn_speed))
+        }
+    }
+
+    /// Mix two analog inputs (`-1..=1`) according to [`Mode`].
+    pub fn mix(&self, x: f64, y: f64) -> (f64, f64) {
+        let x = self.apply_deadzone(x);
+        let y = self.apply_deadzone(y);
+
+        let (left, right) = match self.mode {
+            Mode::Tank => (x, y),
+            Mode::Arcade => desaturate(y + x, y - x),
+            Mode::Curvature if y == 0. => (x, -x),
+            Mode::Curvature => desaturate(y + y.abs() * x, y - y.abs() * x),
+        };
+
+        self.output(left, right)
+    }
+
+    fn apply_deadzone(&self, v: f64) -> f64 {
+        if v.abs() <= self.deadzone {
+            return 0.;
+        }
+        // rescale so the output still starts right after the deadzone
+        v.signum() * (v.abs() - self.deadzone) / (1. - self.deadzone)
+    }
+
+    fn output(&self, left: f64, right: f64) -> (f64, f64) {
+        (
+            (left * self.scale).clamp(-1., 1.),
+            (right * self.scale).clamp(-1., 1.),
+        )
+    }
+}
+
+/// Scale both sides down if either is out of range, so the ratio between them is kept.
+fn desaturate(left: f64, right: f64) -> (f64, f64) {
+    let max = left.abs().max(right.abs());
+    if max > 1. {
+        (left / max, right / max)
+    } else {
+        (left, right)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const W: bool = true;
+    const A: bool = true;
+    const S: bool = true;
+    const D: bool = true;
+    const X: bool = false;
+
+    fn wasd(keys: [bool; 4]) -> Option<(f64, f64)> {
+        DriveMixer::default().wasd(keys, 0.6)
+    }
+
+    #[test]
+    fn wasd_nothing_pressed() {
+        assert_eq!(wasd([X, X, X, X]), None);
+    }
+
+    #[test]
+    fn wasd_single_keys() {
+        assert_eq!(wasd([W, X, X, X]), Some((0.6, 0.6)));
+        assert_eq!(wasd([X, A, X, X]), Some((-0.6, 0.6)));
+        assert_eq!(wasd([X, X, S, X]), Some((-0.6, -0.6)));
+        assert_eq!(wasd([X, X, X, D]), Some((0.6, -0.6)));
+    }
+
+    #[test]
+    fn wasd_diagonals() {
+        assert_eq!(wasd([W, A, X, X]), Some((0.6 / 3., 0.6)));
+        assert_eq!(wasd([W, X, X, D]), Some((0.6, 0.6 / 3.)));
+        assert_eq!(wasd([X, A, S, X]), Some((-0.6 / 3., -0.6)));
+        assert_eq!(wasd([X, X, S, D]), Some((-0.6, -0.6 / 3.)));
+    }
+
+    #[test]
+    fn wasd_opposing_turns_cancel() {
+        assert_eq!(wasd([X, A, X, D]), None);
+        assert_eq!(wasd([W, A, X, D]), Some((0.6, 0.6)));
+        assert_eq!(wasd([X, A, S, D]), Some((-0.6, -0.6)));
+    }
+
+    #[test]
+    fn wasd_w_equals_s_spins_in_place() {
+        assert_eq!(wasd([W, X, S, X]), None);
+        assert_eq!(wasd([W, A, S, X]), Some((-0.6, 0.6)));
+        assert_eq!(wasd([W, X, S, D]), Some((0.6, -0.6)));
+        assert_eq!(wasd([W, A, S, D]), None);
+    }
+
+    #[test]
+    fn wasd_turn_ratio_and_scale() {
+        let m = DriveMixer {
+            turn_ratio: 0.5,
+            scale: 0.5,
+            ..Default::default()
+        };
+        assert_eq!(m.wasd([W, A, X, X], 1.), Some((0.25, 0.5)));
+        assert_eq!(m.wasd([W, X, X, X], 1.), Some((0.5, 0.5)));
+    }
+
+    #[test]
+    fn tank_passes_through_and_clamps() {
+        let m = DriveMixer::new(Mode::Tank);
+        assert_eq!(m.mix(0.2, -0.4), (0.2, -0.4));
+        assert_eq!(m.mix(3., -2.), (1., -1.));
+    }
+
+    #[test]
+    fn arcade() {
+        let m = DriveMixer::new(Mode::Arcade);
+        assert_eq!(m.mix(0., 1.), (1., 1.));
+        assert_eq!(m.mix(1., 0.), (1., -1.));
+        assert_eq!(m.mix(-0.5, 0.5), (0., 1.));
+        // saturated inputs keep their ratio
+        assert_eq!(m.mix(1., 1.), (1., 0.));
+        assert_eq!(m.mix(0.5, 1.), (1., 0.5 / 1.5));
+    }
+
+    #[test]
+    fn curvature() {
+        let m = DriveMixer::new(Mode::Curvature);
+        assert_eq!(m.mix(0., 0.5), (0.5, 0.5));
+        assert_eq!(m.mix(0.5, 0.5), (0.75, 0.25));
+        // reversing curves like a car would
+        assert_eq!(m.mix(-0.5, -0.5), (-0.75, -0.25));
+        // no throttle turns in place
+        assert_eq!(m.mix(0.5, 0.), (0.5, -0.5));
+    }
+
+    #[test]
+    fn deadzone() {
+        let m = DriveMixer {
+            deadzone: 0.5,
+            ..Default::default()
+        };
+        assert_eq!(m.mix(0.1, -0.5), (0., 0.));
+        assert_eq!(m.mix(0.75, -1.), (0.5, -1.));
+    }
+}
@@ -0,0 +1,124 @@
+use crate::{roland, Pose};
+
+/// How drive commands turn into motion, the speed is best measured with a calibration run
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Calibration {
+    /// distance between the wheels in meters
+    pub wheel_base: f64,
+    /// how fast a wheel driven at 1 goes, in m/s
+    pub max_speed: f64,
+}
+
+impl Default for Calibration {
+    /// The Roland's wheel base, with a rough guess at its speed
+    fn default() -> Self {
+        Self {
+            wheel_base: roland::WHEEL_BASE,
+            max_speed: 0.5,
+        }
+    }
+}
+
+impl Calibration {
+    /// Seconds driving straight at `speed` (`0..=1`) should take to cover `distance` meters
+    pub fn time_for(&self, distance: f64, speed: f64) -> f64 {
+        distance / (speed * self.max_speed)
+    }
+
+    /// Correct the speed after driving straight at `speed` for `secs` covered `measured` meters
+    pub fn correct_speed(&mut self, speed: f64, secs: f64, measured: f64) {
+        self.max_speed = measured / (speed * secs);
+    }
+}
+
+/// Dead reckoning: where the robot should be, from the wheel speeds it was told to drive at.
+///
+/// Report every drive command with [`drive`](Self::drive) and the time passing with
+/// [`advance`](Self::advance). Wheels slip and motors differ, so the estimate drifts the longer
+/// it runs, [`reset`](Self::reset) it at a known spot.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Odometry {
+    pub calibration: Calibration,
+    pose: Pose,
+    wheels: (f64, f64),
+}
+
+impl Odometry {
+    pub fn new(calibration: Calibration) -> Self {
+        Self {
+            calibration,
+            ..Default::default()
+        }
+    }
+
+    pub fn pose(&self) -> Pose {
+        self.pose
+    }
+
+    /// Start over from `pose`, the wheels keep turning
+    pub fn reset(&mut self, pose: Pose) {
+        self.pose = pose;
+    }
+
+    /// A drive command was sent, in `-1..=1` like the robot takes them, stopping is `(0, 0)`
+    pub fn drive(&mut self, left: f64, right: f64) {
+        self.wheels = (left.clamp(-1., 1.), right.clamp(-1., 1.));
+    }
+
+    /// The wheel speeds the robot is driving at
+    pub fn wheels(&self) -> (f64, f64) {
+        self.wheels
+    }
+
+    /// Move along at the current wheel speeds for `dt` seconds
+    pub fn advance(&mut self, dt: f64) {
+        let Calibration {
+            wheel_base,
+            max_speed,
+        } = self.calibration;
+        let (left, right) = self.wheels;
+        self.pose = self
+            .pose
+            .advance(left * max_speed, right * max_speed, wheel_base, dt);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    #[test]
+    fn drives_and_stops() {
+        let mut odo = Odometry::default();
+        odo.drive(0.5, 0.5);
+        odo.advance(2.);
+        assert!((odo.pose().x - 0.5).abs() < 1e-9);
+        odo.drive(0., 0.);
+        odo.advance(10.);
+        assert!((odo.pose().x - 0.5).abs() < 1e-9);
+        assert_eq!(odo.pose().y, 0.);
+    }
+
+    #[test]
+    fn turns() {
+        let mut odo = Odometry::default();
+        // the wheels go around a circle of the wheel base, half of it is a half turn
+        let secs = 0.15 * PI / 2. / 0.5;
+        odo.drive(-1., 1.);
+        odo.advance(secs);
+        assert!((odo.pose().heading - PI).abs() < 1e-9);
+        assert!(odo.pose().x.abs() < 1e-9);
+    }
+
+    #[test]
+    fn calibration() {
+        let mut cal = Calibration::default();
+        let secs = cal.time_for(1., 0.5);
+        assert!((secs - 4.).abs() < 1e-9);
+        // it only went 80cm
+        cal.correct_speed(0.5, secs, 0.8);
+        assert!((cal.max_speed - 0.4).abs() < 1e-9);
+        assert!((cal.time_for(0.8, 0.5) - 4.).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,102 @@
+/// Where a robot is on the floor: meters, with the heading in radians counterclockwise from
+/// the x axis.
+///
+/// [`advance`](Self::advance) moves it with differential drive kinematics, so it works for a
+/// simulated robot and for estimating where a real one went.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Pose {
+    pub x: f64,
+    pub y: f64,
+    pub heading: f64,
+}
+
+impl Pose {
+    pub fn new(x: f64, y: f64, heading: f64) -> Self {
+        Self { x, y, heading }
+    }
+
+    /// The pose after driving the wheels at `left` and `right` m/s for `dt` seconds, with the
+    /// wheels `wheel_base` meters apart
+    pub fn advance(self, left: f64, right: f64, wheel_base: f64, dt: f64) -> Self {
+        let forward = (left + right) / 2. * dt;
+        let turn = (right - left) / wheel_base * dt;
+
+        // move along the chord of the arc, which is exact for constant wheel speeds
+        let (dx, dy) = if turn.abs() < 1e-9 {
+            (forward, 0.)
+        } else {
+            let chord = 2. * forward / turn * (turn / 2.).sin();
+            let (s, c) = (turn / 2.).sin_cos();
+            (chord * c, chord * s)
+        };
+        let (s, c) = self.heading.sin_cos();
+        Self {
+            x: self.x + dx * c - dy * s,
+            y: self.y + dx * s + dy * c,
+            heading: wrap_angle(self.heading + turn),
+        }
+    }
+
+    /// A point given relative to the robot, `x` ahead and `y` to the left, on the floor
+    pub fn transform(&self, x: f64, y: f64) -> (f64, f64) {
+        let (s, c) = self.heading.sin_cos();
+        (self.x + x * c - y * s, self.y + x * s + y * c)
+    }
+}
+
+/// Bring an angle into `-π..=π`
+pub fn wrap_angle(a: f64) -> f64 {
+    use std::f64::consts::{PI, TAU};
+    let a = a.rem_euclid(TAU);
+    if a > PI {
+        a - TAU
+    } else {
+        a
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::{FRAC_PI_2, PI};
+
+    fn close(a: Pose, b: Pose) -> bool {
+        (a.x - b.x).abs() < 1e-9
+            && (a.y - b.y).abs() < 1e-9
+            && wrap_angle(a.heading - b.heading).abs() < 1e-9
+    }
+
+    #[test]
+    fn straight() {
+        let p = Pose::new(1., 1., FRAC_PI_2).advance(0.5, 0.5, 0.15, 2.);
+        assert!(close(p, Pose::new(1., 2., FRAC_PI_2)));
+    }
+
+    #[test]
+    fn spin_in_place() {
+        // a quarter turn left: the wheels travel a quarter of the circle they turn on
+        let arc = 0.15 * PI / 4.;
+        let p = Pose::default().advance(-arc, arc, 0.15, 1.);
+        assert!(close(p, Pose::new(0., 0., FRAC_PI_2)));
+    }
+
+    #[test]
+    fn arc() {
+        // half a circle of radius 1 to the left
+        let (r, base) = (1., 0.2);
+        let (left, right) = ((r - base / 2.) * PI, (r + base / 2.) * PI);
+        let p = Pose::default().advance(left, right, base, 1.);
+        assert!(close(p, Pose::new(0., 2., PI)));
+
+        // the same in small steps ends up in the same place
+        let stepped = (0..100).fold(Pose::default(), |p, _| p.advance(left, right, base, 0.01));
+        assert!(close(p, stepped));
+    }
+
+    #[test]
+    fn transform() {
+        let p = Pose::new(1., 0., FRAC_PI_2);
+        let (x, y) = p.transform(0.5, 0.1);
+        assert!((x - 0.9).abs() < 1e-9 && (y - 0.5).abs() < 1e-9);
+    }
+}
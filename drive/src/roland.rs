@@ -0,0 +1,36 @@
+//! The Roland's measurements in meters, relative to its center with `x` ahead and `y` to the
+//! left.
+
+/// distance between the wheels
+pub const WHEEL_BASE: f64 = 0.15;
+/// it's about a circle this big
+pub const RADIUS: f64 = 0.1;
+/// the track sensors, left to right
+pub const TRACK_SENSORS: [(f64, f64); 4] =
+    [(0.08, 0.024), (0.08, 0.008), (0.08, -0.008), (0.08, -0.024)];
+/// where the ultrasonic sensor sits on the camera servo
+pub const ULTRA_MOUNT: (f64, f64) = (0.1, 0.);
+
+/// The wheel speeds `MoveRobotByAngle` drives at: the path bends by `angle` degrees, up to 90
+/// which turns on the spot
+pub fn by_angle(angle: f64, speed: f64) -> (f64, f64) {
+    let turn = (angle / 90.).clamp(-1., 1.);
+    (
+        speed * (1. + 2. * turn).min(1.),
+        speed * (1. - 2. * turn).min(1.),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn angles() {
+        assert_eq!(by_angle(0., 0.5), (0.5, 0.5));
+        assert_eq!(by_angle(90., 0.5), (0.5, -0.5));
+        assert_eq!(by_angle(-90., 0.5), (-0.5, 0.5));
+        // a gentle bend slows the inner wheel
+        assert_eq!(by_angle(22.5, 1.), (1., 0.5));
+    }
+}
@@ -0,0 +1,116 @@
+use std::time::{Duration, Instant};
+
+/// How often the clients should call [`Watchdog::tick`]
+pub const RESEND_INTERVAL: Duration = Duration::from_millis(200);
+
+/// What the client should send to the robot on a watchdog tick
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    /// re-send the current drive command
+    Drive(f64, f64),
+    /// not driving, just keep the connection alive
+    Nop,
+    /// no input for too long, stop the robot
+    Stop,
+}
+
+/// Dead-man switch for a driving client.
+///
+/// The client reports every input with [`feed`](Self::feed) and every drive command with
+/// [`drive`](Self::drive), then periodically sends whatever [`tick`](Self::tick) returns, so a
+/// robot only keeps moving while its controller is alive and being used.
+#[derive(Debug, Clone)]
+pub struct Watchdog {
+    /// `None` disables the input timeout, the last command is still re-sent
+    timeout: Option<Duration>,
+    last_input: Instant,
+    drive: Option<(f64, f64)>,
+    tripped: bool,
+}
+
+impl Watchdog {
+    pub fn new(timeout: Option<Duration>) -> Self {
+        Self {
+            timeout,
+            last_input: Instant::now(),
+            drive: None,
+            tripped: false,
+        }
+    }
+
+    /// Some input arrived from the user
+    pub fn feed(&mut self) {
+        self.last_input = Instant::now();
+    }
+
+    /// A drive command was sent, `None` means stop
+    pub fn drive(&mut self, cmd: Option<(f64, f64)>) {
+        self.feed();
+        self.drive = cmd;
+        self.tripped = false;
+    }
+
+    /// The robot was stopped because of the input timeout, and hasn't been driven since
+    pub fn tripped(&self) -> bool {
+        self.tripped
+    }
+
+    pub fn tick(&mut self) -> Action {
+        self.tick_at(Instant::now())
+    }
+
+    fn tick_at(&mut self, now: Instant) -> Action {
+        let timed_out = self
+            .timeout
+            .is_some_and(|t| now.duration_since(self.last_input) > t);
+
+        match self.drive {
+            Some(_) if timed_out => {
+                self.drive = None;
+                self.tripped = true;
+                Action::Stop
+            }
+            Some((left, right)) => Action::Drive(left, right),
+            None => Action::Nop,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(1);
+
+    #[test]
+    fn resends_while_fed() {
+        let mut w = Watchdog::new(Some(TIMEOUT));
+        assert_eq!(w.tick(), Action::Nop);
+        w.drive(Some((0.5, 0.5)));
+        assert_eq!(w.tick(), Action::Drive(0.5, 0.5));
+        w.drive(None);
+        assert_eq!(w.tick(), Action::Nop);
+    }
+
+    #[test]
+    fn stops_once_on_timeout() {
+        let mut w = Watchdog::new(Some(TIMEOUT));
+        w.drive(Some((0.5, -0.5)));
+        let later = w.last_input + TIMEOUT * 2;
+        assert_eq!(w.tick_at(later), Action::Stop);
+        assert!(w.tripped());
+        assert_eq!(w.tick_at(later), Action::Nop);
+
+        w.drive(Some((1., 1.)));
+        assert!(!w.tripped());
+        assert_eq!(w.tick(), Action::Drive(1., 1.));
+    }
+
+    #[test]
+    fn no_timeout() {
+        let mut w = Watchdog::new(None);
+        w.drive(Some((0.5, 0.5)));
+        let later = w.last_input + Duration::from_secs(3600);
+        assert_eq!(w.tick_at(later), Action::Drive(0.5, 0.5));
+    }
+}
@@ -1,3 +1,4 @@
+use drive::{DriveMixer, Mode};
 use roblib_client::{
     roblib::{
         cmd::{self, Command},
@@ -9,9 +10,12 @@ use roblib_client::{
 };
 
 static WEIGHTS: [f64; 4] = [-2., -1., 1., 2.];
+static MIXER: DriveMixer = DriveMixer::new(Mode::Arcade);
 
 fn main() -> Result<()> {
-    let robot = Box::leak(Box::new(Robot::new(Tcp::connect("roland:1110")?)));
+    // point it at a simulator with `ROBLIB_ADDR=localhost:1110`
+    let addr = std::env::var("ROBLIB_ADDR").unwrap_or_else(|_| "roland:1110".into());
+    let robot = Box::leak(Box::new(Robot::new(Tcp::connect(&addr)?)));
 
     let on_track = |d: <cmd::TrackSensor as Command>::Return| {
         if d == [true, true, true, true] {
@@ -20,8 +24,8 @@ fn main() -> Result<()> {
             return Ok(());
         }
 
-        let mut left = 0.;
-        let mut right = 0.;
+        let mut throttle = 0.;
+        let mut turn = 0.;
 
         // if !d[0] {
         //     right += 0.2;
@@ -40,14 +44,14 @@ fn main() -> Result<()> {
         //     .enumerate()
         //     .map(|(i, b)| (!b as u8 as f64) * WEIGHTS[i]);
 
-        for (b, w) in d.iter().filter(|b| !**b).zip(WEIGHTS.iter()) {
-            if *w < 0. {
-                left += -w;
-            } else {
-                right += w;
-            }
+        // every sensor on the line pushes forward, the outer ones harder, and steers by its weight
+        for (_, w) in d.iter().filter(|b| !**b).zip(WEIGHTS.iter()) {
+            throttle += w.abs() / 2.;
+            turn -= w / 2.;
         }
 
+        // scales both wheels down together when the sum is too much, so the turn is kept
+        let (left, right) = MIXER.mix(turn, throttle);
         println!("{d:?}, {left}:{right}");
         robot.drive(left, right)?;
 
@@ -1,11 +1,33 @@
 use anyhow::Result;
-use roblib_client::{roblib::roland::Roland, transports::tcp::Tcp, Robot};
+use drive::{
+    watchdog::{Action, RESEND_INTERVAL},
+    CollisionGuard, DriveMixer, Watchdog,
+};
+use roblib_client::{
+    roblib::{cmd, roland::Roland},
+    transports::{tcp::Tcp, Transport},
+    Robot,
+};
 use std::{
     io::{BufRead, BufReader},
+    sync::{Arc, Mutex},
     time::Duration,
 };
 
 const BAUD: u32 = 115_200;
+const GUARD_INTERVAL: Duration = Duration::from_millis(100);
+/// collision guard distance in cm when `ROBLIB_GUARD` isn't set
+const GUARD_THRESHOLD: f64 = 30.;
+
+/// Stops the robot when main returns or panics
+struct StopOnDrop(Arc<Robot<Tcp>>);
+impl Drop for StopOnDrop {
+    fn drop(&mut self) {
+        if let Err(e) = self.0.stop() {
+            eprintln!("Failed to stop the robot: {e:?}");
+        }
+    }
+}
 
 fn main() -> Result<()> {
     let serial = serialport::new("/dev/ttyACM0", BAUD)
@@ -13,7 +35,81 @@ fn main() -> Result<()> {
         .open()
         .expect("Failed to open port");
 
-    let mut robot = Robot::new(Tcp::connect("roland:1110")?);
+    // point it at a simulator with `ROBLIB_ADDR=localhost:1110`
+    let addr = std::env::var("ROBLIB_ADDR").unwrap_or_else(|_| "roland:1110".into());
+    let robot = Arc::new(Robot::new(Tcp::connect(&addr)?));
+    let _stop = StopOnDrop(robot.clone());
+
+    // milliseconds without serial input before the robot is stopped
+    let timeout = match std::env::var("ROBLIB_WATCHDOG") {
+        Ok(ms) => Some(Duration::from_millis(ms.parse()?)),
+        Err(_) => None,
+    };
+    let watchdog = Arc::new(Mutex::new(Watchdog::new(timeout)));
+
+    // centimeters, setting it turns the collision guard on, `guard 1` and `guard 0` toggle it
+    let guard = match std::env::var("ROBLIB_GUARD") {
+        Ok(cm) => CollisionGuard::new(cm.parse::<f64>()? / 100.),
+        Err(_) => CollisionGuard {
+            enabled: false,
+            ..CollisionGuard::new(GUARD_THRESHOLD / 100.)
+        },
+    };
+    let guard = Arc::new(Mutex::new(guard));
+
+    {
+        let robot = robot.clone();
+        ctrlc::set_handler(move || {
+            if let Err(e) = robot.stop() {
+                eprintln!("Failed to stop the robot: {e:?}");
+            }
+            std::process::exit(0);
+        })?;
+    }
+    {
+        let robot = robot.clone();
+        let watchdog = watchdog.clone();
+        let guard = guard.clone();
+        std::thread::spawn(move || loop {
+            std::thread::sleep(RESEND_INTERVAL);
+            let action = watchdog.lock().unwrap().tick();
+            let res = match action {
+                // picks up changes of the guard too
+                Action::Drive(left, right) => {
+                    let (left, right) = guard.lock().unwrap().filter(left, right);
+                    robot.drive(left, right)
+                }
+                Action::Nop => robot.transport.cmd(cmd::Nop),
+                Action::Stop => {
+                    eprintln!("No input for too long, stopping the robot");
+                    robot.stop()
+                }
+            };
+            if let Err(e) = res {
+                eprintln!("{:?}", e);
+            }
+        });
+    }
+
+    {
+        let robot = robot.clone();
+        let guard = guard.clone();
+        std::thread::spawn(move || loop {
+            std::thread::sleep(GUARD_INTERVAL);
+            if !guard.lock().unwrap().enabled {
+                continue;
+            }
+            match robot.transport.cmd(cmd::UltraSensor) {
+                Ok(d) => {
+                    let mut guard = guard.lock().unwrap();
+                    if guard.update(d) && guard.blocking() {
+                        eprintln!("Obstacle at {:.0}cm, not driving forward", d * 100.);
+                    }
+                }
+                Err(e) => eprintln!("{:?}", e),
+            }
+        });
+    }
 
     let mut reader = BufReader::new(serial);
     let mut buf = String::new();
@@ -21,18 +117,22 @@ fn main() -> Result<()> {
 
     loop {
         match reader.read_line(&mut buf) {
+            Ok(0) => anyhow::bail!("Serial port closed"),
             Ok(n) => {
-                handle_line(&mut state, &mut robot, &buf[..n])?;
+                watchdog.lock().unwrap().feed();
+                handle_line(&mut state, &robot, &watchdog, &guard, &buf[..n])?;
                 buf.clear();
             }
             Err(ref e) if e.kind() == std::io::ErrorKind::TimedOut => (),
-            Err(e) => eprintln!("{:?}", e),
+            // most likely the cable got pulled
+            Err(e) => return Err(e.into()),
         };
     }
 }
 
 #[derive(Debug, Default)]
 struct State {
+    mixer: DriveMixer,
     speed: u16,
     w: bool,
     a: bool,
@@ -40,7 +140,13 @@ struct State {
     d: bool,
 }
 
-fn handle_line(state: &mut State, robot: &mut Robot<Tcp>, line: &str) -> Result<()> {
+fn handle_line(
+    state: &mut State,
+    robot: &Robot<Tcp>,
+    watchdog: &Mutex<Watchdog>,
+    guard: &Mutex<CollisionGuard>,
+    line: &str,
+) -> Result<()> {
     let mut sp = line.splitn(2, ' ');
     let key = sp.next().unwrap();
     let value = sp.next().unwrap().trim();
@@ -52,6 +158,7 @@ fn handle_line(state: &mut State, robot: &mut Robot<Tcp>, line: &str) -> Result<
         "a" => state.a = value == "1",
         "s" => state.s = value == "1",
         "d" => state.d = value == "1",
+        "guard" => guard.lock().unwrap().enabled = value == "1",
 
         "servo" => {
             let v: f64 = value.parse()?;
@@ -70,40 +177,18 @@ fn handle_line(state: &mut State, robot: &mut Robot<Tcp>, line: &str) -> Result<
         _ => anyhow::bail!("unknown command: {key}"),
     }
 
-    // move in place or don't move at all
-    if state.w == state.s {
-        let speed = state.speed as f64 / 100.;
-        if state.a && !state.d {
-            robot.drive(-speed, speed)?;
-        } else if state.d && !state.a {
-            robot.drive(speed, -speed)?;
-        } else {
-            robot.stop()?;
+    let cmd = state.mixer.wasd(
+        [state.w, state.a, state.s, state.d],
+        state.speed as f64 / 100.,
+    );
+    watchdog.lock().unwrap().drive(cmd);
+    match cmd {
+        Some((left, right)) => {
+            let (left, right) = guard.lock().unwrap().filter(left, right);
+            robot.drive(left, right)?
         }
-        return Ok(());
-    }
-
-    let speed: f64 = if state.w {
-        state.speed as f64 / 100.
-    } else {
-        -(state.speed as f64) / 100.
-    };
-
-    // drive straight
-    if state.a == state.d {
-        robot.drive(speed, speed)?;
-        return Ok(());
-    }
-
-    // diagonal drive
-    let turn_speed = speed / 3.;
-    if state.a {
-        robot.drive(turn_speed, speed)?;
-        return Ok(());
-    } else if state.d {
-        robot.drive(speed, turn_speed)?;
-        return Ok(());
+        None => robot.stop()?,
     }
 
-    unreachable!()
+    Ok(())
 }
@@ -0,0 +1,96 @@
+mod proto;
+mod server;
+mod view;
+mod world;
+
+use anyhow::Result;
+use clap::Parser;
+use drive::Calibration;
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::net::TcpListener;
+use world::{Map, World};
+
+/// how often the robot is moved
+const STEP: Duration = Duration::from_millis(10);
+
+/// A pretend Roland on a 2D map, for running the clients without a robot
+#[derive(Debug, Parser)]
+#[command(author, version)]
+struct Args {
+    /// Address to listen on
+    #[arg(long, default_value = "127.0.0.1")]
+    bind: String,
+
+    #[arg(short, long, env = "ROBLIB_PORT", default_value_t = 1110)]
+    port: u16,
+
+    /// Port for the view feed, which tells roblib-tui's Map tab where the robot really is
+    #[arg(long, default_value_t = 1111)]
+    view_port: u16,
+
+    /// TOML file with the line, the walls and where to start, an oval in a room by default
+    #[arg(short, long)]
+    map: Option<PathBuf>,
+
+    /// Speed of a wheel driven at full power, in m/s
+    #[arg(long, default_value_t = 0.5)]
+    max_speed: f64,
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+    let map = match &args.map {
+        Some(path) => Map::load(path)?,
+        None => Map::default(),
+    };
+    let calibration = Calibration {
+        max_speed: args.max_speed,
+        ..Default::default()
+    };
+    let world = Arc::new(Mutex::new(World::new(map, calibration)));
+    let started = Instant::now();
+
+    {
+        let world = world.clone();
+        tokio::spawn(async move {
+            let mut tick = tokio::time::interval(STEP);
+            loop {
+                tick.tick().await;
+                world.lock().unwrap().step(STEP.as_secs_f64());
+            }
+        });
+    }
+
+    let listener = TcpListener::bind((args.bind.as_str(), args.port)).await?;
+    let view = TcpListener::bind((args.bind.as_str(), args.view_port)).await?;
+    println!("Simulated Roland listening on {}", listener.local_addr()?);
+    println!("View feed on {}", view.local_addr()?);
+    loop {
+        let (stream, addr) = tokio::select! {
+            _ = tokio::signal::ctrl_c() => break,
+            res = view.accept() => {
+                let (stream, _) = res?;
+                // it ends when the viewer hangs up, nothing to report
+                tokio::spawn(view::serve(world.clone(), stream));
+                continue;
+            }
+            res = listener.accept() => res?,
+        };
+        println!("{addr} connected");
+        let world = world.clone();
+        tokio::spawn(async move {
+            match server::serve(world, started, stream).await {
+                Ok(()) => println!("{addr} disconnected"),
+                Err(e) => eprintln!("{addr} disconnected: {e:#}"),
+            }
+        });
+    }
+
+    println!("Bye!");
+    Ok(())
+}
@@ -0,0 +1,99 @@
+//! The framing roblib's TCP transport uses: every message is a big-endian `u32` length followed
+//! by that many bytes of bincode.
+//!
+//! Clients send `(id, Concrete)`. Commands that return something are answered with
+//! `(id, return value)`, and events are sent as `(id, value)` with the id of the subscribe
+//! command.
+
+use anyhow::{bail, Result};
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// nothing roblib sends comes close, anything bigger is a broken stream
+const MAX_FRAME: usize = 64 * 1024;
+
+/// Read a message, `None` when the client hung up
+pub async fn read<T: DeserializeOwned>(r: &mut (impl AsyncRead + Unpin)) -> Result<Option<T>> {
+    let len = match r.read_u32().await {
+        Ok(len) => len as usize,
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    if len > MAX_FRAME {
+        bail!("Frame of {len} bytes is too big");
+    }
+    let mut buf = vec![0; len];
+    r.read_exact(&mut buf).await?;
+    Ok(Some(bincode::deserialize(&buf)?))
+}
+
+/// Encode a message with its length, ready to write
+pub fn encode<T: Serialize>(msg: &T) -> Result<Vec<u8>> {
+    let body = bincode::serialize(msg)?;
+    let mut frame = (body.len() as u32).to_be_bytes().to_vec();
+    frame.extend(body);
+    Ok(frame)
+}
+
+pub async fn write(w: &mut (impl AsyncWrite + Unpin), frame: &[u8]) -> Result<()> {
+    w.write_all(frame).await?;
+    w.flush().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        server,
+        world::{Map, World},
+    };
+    use drive::Calibration;
+    use roblib_client::{
+        roblib::{event, roland::RolandAsync},
+        transports::tcp::TcpAsync,
+        RobotAsync,
+    };
+    use std::{
+        sync::{Arc, Mutex},
+        time::{Duration, Instant},
+    };
+    use tokio::{net::TcpListener, time::timeout};
+
+    /// roblib's own client against the server, so the framing can't drift from the real thing
+    #[tokio::test]
+    async fn roblib_client() {
+        let world = Arc::new(Mutex::new(World::new(
+            Map::default(),
+            Calibration::default(),
+        )));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        {
+            let world = world.clone();
+            tokio::spawn(async move {
+                let (stream, _) = listener.accept().await.unwrap();
+                server::serve(world, Instant::now(), stream).await.unwrap();
+            });
+        }
+        let robot = RobotAsync::new(TcpAsync::connect(&addr).await.unwrap());
+
+        let (track, ultra) = {
+            let world = world.lock().unwrap();
+            (world.track_sensors(), world.ultra_sensor())
+        };
+        assert_eq!(robot.track_sensor().await.unwrap(), track);
+        assert_eq!(robot.ultra_sensor().await.unwrap(), ultra);
+
+        // commands are handled in order, so it's driving by the time the next one is answered
+        robot.drive(0.5, -0.25).await.unwrap();
+        robot.track_sensor().await.unwrap();
+        assert_eq!(world.lock().unwrap().odometry.wheels(), (0.5, -0.25));
+
+        let mut rx = robot
+            .subscribe(event::UltraSensor(Duration::from_millis(20)))
+            .await
+            .unwrap();
+        let value = timeout(Duration::from_secs(1), rx.recv()).await.unwrap();
+        assert_eq!(value.unwrap(), ultra);
+    }
+}
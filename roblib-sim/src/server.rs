@@ -0,0 +1,196 @@
+use crate::{proto, world::World};
+use anyhow::Result;
+use drive::roland;
+use roblib::{
+    cmd::{self, Concrete},
+    event::ConcreteType,
+};
+use serde::Serialize;
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::{
+    net::TcpStream,
+    sync::mpsc::{self, UnboundedSender},
+    task::JoinHandle,
+};
+
+/// how often change events look at the world
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+type Out = UnboundedSender<Vec<u8>>;
+
+/// One connected client
+struct Client {
+    world: Arc<Mutex<World>>,
+    addr: SocketAddr,
+    started: Instant,
+    out: Out,
+    subs: Vec<(ConcreteType, JoinHandle<()>)>,
+}
+
+/// Answer a client's commands until it disconnects
+pub async fn serve(world: Arc<Mutex<World>>, started: Instant, stream: TcpStream) -> Result<()> {
+    let addr = stream.peer_addr()?;
+    let (mut rx, mut tx) = stream.into_split();
+    let (out, mut frames) = mpsc::unbounded_channel::<Vec<u8>>();
+    // replies and events both go through here, so frames don't interleave
+    let writer = tokio::spawn(async move {
+        while let Some(frame) = frames.recv().await {
+            proto::write(&mut tx, &frame).await?;
+        }
+        anyhow::Ok(())
+    });
+
+    let mut client = Client {
+        world,
+        addr,
+        started,
+        out,
+        subs: vec![],
+    };
+    let res = async {
+        while let Some((id, cmd)) = proto::read::<(u32, Concrete)>(&mut rx).await? {
+            client.execute(id, cmd)?;
+        }
+        anyhow::Ok(())
+    }
+    .await;
+
+    // like the real robot, don't keep driving for a client that's gone, unless someone else
+    // has taken over since
+    {
+        let mut world = client.world.lock().unwrap();
+        if world.driver == Some(addr) {
+            world.odometry.drive(0., 0.);
+            world.driver = None;
+        }
+    }
+    for (_, task) in client.subs {
+        task.abort();
+    }
+    writer.abort();
+    res
+}
+
+impl Client {
+    fn execute(&mut self, id: u32, cmd: Concrete) -> Result<()> {
+        let mut world = self.world.lock().unwrap();
+        if matches!(
+            cmd,
+            Concrete::MoveRobot(_)
+                | Concrete::MoveRobotByAngle(_)
+                | Concrete::StopRobot(_)
+                | Concrete::Abort(_)
+        ) {
+            world.driver = Some(self.addr);
+        }
+        match cmd {
+            Concrete::MoveRobot(cmd::MoveRobot(left, right)) => world.odometry.drive(left, right),
+            Concrete::MoveRobotByAngle(cmd::MoveRobotByAngle(angle, speed)) => {
+                let (left, right) = roland::by_angle(angle, speed);
+                world.odometry.drive(left, right);
+            }
+            Concrete::StopRobot(_) | Concrete::Abort(_) => world.odometry.drive(0., 0.),
+            Concrete::Led(cmd::Led(r, g, b)) => world.led = [r, g, b],
+            Concrete::RolandServo(cmd::RolandServo(deg)) => world.servo = deg.clamp(-90., 90.),
+            Concrete::Buzzer(cmd::Buzzer(pw)) => world.buzzer = pw,
+            Concrete::TrackSensor(_) => return self.reply(id, world.track_sensors()),
+            Concrete::UltraSensor(_) => return self.reply(id, world.ultra_sensor()),
+            // there's nothing on the pins, inputs read whatever was written to them last
+            Concrete::PinMode(_) | Concrete::Pwm(_) | Concrete::Servo(_) | Concrete::Nop(_) => (),
+            Concrete::ReadPin(cmd::ReadPin(pin)) => {
+                return self.reply(id, world.pins.get(&pin).copied().unwrap_or(false))
+            }
+            Concrete::WritePin(cmd::WritePin(pin, level)) => {
+                world.pins.insert(pin, level);
+            }
+            Concrete::GetUptime(_) => return self.reply(id, self.started.elapsed()),
+            Concrete::Subscribe(cmd::Subscribe(event)) => {
+                drop(world);
+                self.subscribe(id, event);
+            }
+            Concrete::Unsubscribe(cmd::Unsubscribe(event)) => {
+                self.subs.retain(|(e, task)| {
+                    let same = match (e, &event) {
+                        (ConcreteType::TrackSensor(_), ConcreteType::TrackSensor(_)) => true,
+                        (ConcreteType::UltraSensor(a), ConcreteType::UltraSensor(b)) => a.0 == b.0,
+                        (ConcreteType::GpioPin(a), ConcreteType::GpioPin(b)) => a.0 == b.0,
+                        _ => false,
+                    };
+                    if same {
+                        task.abort();
+                    }
+                    !same
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn reply(&self, id: u32, value: impl Serialize) -> Result<()> {
+        // the writer only stops when the connection does
+        let _ = self.out.send(proto::encode(&(id, value))?);
+        Ok(())
+    }
+
+    fn subscribe(&mut self, id: u32, event: ConcreteType) {
+        let (world, out) = (self.world.clone(), self.out.clone());
+        let task = match &event {
+            ConcreteType::TrackSensor(_) => tokio::spawn(on_change(id, out, move || {
+                world.lock().unwrap().track_sensors()
+            })),
+            ConcreteType::UltraSensor(e) => tokio::spawn(periodic(id, out, e.0, move || {
+                world.lock().unwrap().ultra_sensor()
+            })),
+            ConcreteType::GpioPin(e) => {
+                let pin = e.0;
+                tokio::spawn(on_change(id, out, move || {
+                    world
+                        .lock()
+                        .unwrap()
+                        .pins
+                        .get(&pin)
+                        .copied()
+                        .unwrap_or(false)
+                }))
+            }
+        };
+        self.subs.push((event, task));
+    }
+}
+
+/// Send the value every time it changes
+async fn on_change<T: PartialEq + Serialize>(id: u32, out: Out, mut get: impl FnMut() -> T) {
+    let mut last = get();
+    let mut tick = tokio::time::interval(POLL_INTERVAL);
+    loop {
+        tick.tick().await;
+        let value = get();
+        if value == last {
+            continue;
+        }
+        if !send(&out, id, &value) {
+            break;
+        }
+        last = value;
+    }
+}
+
+/// Send the value every `period`
+async fn periodic<T: Serialize>(id: u32, out: Out, period: Duration, mut get: impl FnMut() -> T) {
+    let mut tick = tokio::time::interval(period.max(POLL_INTERVAL));
+    loop {
+        tick.tick().await;
+        if !send(&out, id, &get()) {
+            break;
+        }
+    }
+}
+
+/// Send an event, returns `false` once the client is gone
+fn send(out: &Out, id: u32, value: &impl Serialize) -> bool {
+    proto::encode(&(id, value)).is_ok_and(|frame| out.send(frame).is_ok())
+}
@@ -0,0 +1,57 @@
+//! A feed of the simulation for drawing it, separate from the robot protocol since a real robot
+//! doesn't know where it is.
+//!
+//! Each client gets JSON lines: the map once, then where the robot is every [`INTERVAL`].
+
+use crate::world::{Map, World};
+use anyhow::Result;
+use serde::Serialize;
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{io::AsyncWriteExt, net::TcpStream};
+
+const INTERVAL: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum View<'a> {
+    Map(&'a Map),
+    Pose {
+        x: f64,
+        y: f64,
+        /// radians counterclockwise from the x axis
+        heading: f64,
+        /// camera servo angle in degrees
+        servo: f64,
+    },
+}
+
+pub async fn serve(world: Arc<Mutex<World>>, mut stream: TcpStream) -> Result<()> {
+    let map = world.lock().unwrap().map.clone();
+    send(&mut stream, &View::Map(&map)).await?;
+
+    let mut tick = tokio::time::interval(INTERVAL);
+    loop {
+        tick.tick().await;
+        let pose = {
+            let world = world.lock().unwrap();
+            let pose = world.pose();
+            View::Pose {
+                x: pose.x,
+                y: pose.y,
+                heading: pose.heading,
+                servo: world.servo,
+            }
+        };
+        send(&mut stream, &pose).await?;
+    }
+}
+
+async fn send(stream: &mut TcpStream, view: &View<'_>) -> Result<()> {
+    let mut line = serde_json::to_vec(view)?;
+    line.push(b'\n');
+    stream.write_all(&line).await?;
+    Ok(())
+}
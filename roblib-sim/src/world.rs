@@ -0,0 +1,231 @@
+use anyhow::{Context, Result};
+use drive::{
+    roland::{RADIUS, TRACK_SENSORS, ULTRA_MOUNT},
+    Calibration, Odometry, Pose,
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, net::SocketAddr, path::Path};
+
+/// the ultrasonic sensor doesn't see further than this, in meters
+pub const ULTRA_RANGE: f64 = 4.;
+
+/// What the simulated robot drives around on. Lengths are in meters.
+///
+/// ```toml
+/// line = [[0.5, 0.5], [2.5, 0.5], [2.5, 1.5], [0.5, 1.5], [0.5, 0.5]]
+/// line_width = 0.02
+/// walls = [[[0, 0], [3, 0], [3, 2], [0, 2], [0, 0]]]
+/// # x, y and heading in degrees
+/// start = [1.5, 0.5, 0]
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Map {
+    /// the line to follow, close it by ending where it starts
+    pub line: Vec<[f64; 2]>,
+    pub line_width: f64,
+    /// each one is a polyline
+    pub walls: Vec<Vec<[f64; 2]>>,
+    pub start: [f64; 3],
+}
+
+impl Default for Map {
+    /// An oval line in a 3 by 2 meter room
+    fn default() -> Self {
+        let line = (0..=48)
+            .map(|i| {
+                let a = i as f64 / 48. * std::f64::consts::TAU;
+                [1.5 + a.cos(), 1. + 0.6 * a.sin()]
+            })
+            .collect();
+        Self {
+            line,
+            line_width: 0.02,
+            walls: vec![vec![[0., 0.], [3., 0.], [3., 2.], [0., 2.], [0., 0.]]],
+            start: [2.5, 1., 90.],
+        }
+    }
+}
+
+impl Map {
+    pub fn load(path: &Path) -> Result<Self> {
+        let src = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        toml::from_str(&src).with_context(|| format!("Invalid map in {}", path.display()))
+    }
+
+    pub fn start(&self) -> Pose {
+        let [x, y, heading] = self.start;
+        Pose::new(x, y, heading.to_radians())
+    }
+
+    fn wall_segments(&self) -> impl Iterator<Item = ([f64; 2], [f64; 2])> + '_ {
+        self.walls
+            .iter()
+            .flat_map(|w| w.windows(2).map(|s| (s[0], s[1])))
+    }
+}
+
+/// The simulated Roland and everything it can sense
+#[derive(Debug, Clone)]
+pub struct World {
+    pub map: Map,
+    /// moves the robot exactly as driven, which is the truth here, walls aside
+    pub odometry: Odometry,
+    /// the client that sent the last drive command, only it leaving stops the robot
+    pub driver: Option<SocketAddr>,
+    pub led: [bool; 3],
+    /// buzzer pulse width, 1 is silent
+    pub buzzer: f64,
+    /// camera servo angle in degrees, positive is to the left
+    pub servo: f64,
+    /// GPIO pin levels, writing an output sets it, reading an unset input gives low
+    pub pins: HashMap<u8, bool>,
+}
+
+impl World {
+    pub fn new(map: Map, calibration: Calibration) -> Self {
+        let mut odometry = Odometry::new(calibration);
+        odometry.reset(map.start());
+        Self {
+            map,
+            odometry,
+            driver: None,
+            led: [false; 3],
+            buzzer: 1.,
+            servo: 0.,
+            pins: HashMap::new(),
+        }
+    }
+
+    pub fn pose(&self) -> Pose {
+        self.odometry.pose()
+    }
+
+    /// Move the robot for `dt` seconds, it stops at walls instead of going through them
+    pub fn step(&mut self, dt: f64) {
+        let mut next = self.odometry.clone();
+        next.advance(dt);
+        let to = next.pose();
+        let hits = self
+            .map
+            .wall_segments()
+            .any(|(a, b)| segment_distance([to.x, to.y], a, b) < RADIUS);
+        if hits {
+            // still let it turn on the spot
+            let pose = self.pose();
+            self.odometry.reset(Pose::new(pose.x, pose.y, to.heading));
+        } else {
+            self.odometry = next;
+        }
+    }
+
+    /// `false` for the sensors over the line, like the real ones that see it as dark
+    pub fn track_sensors(&self) -> [bool; 4] {
+        let pose = self.pose();
+        TRACK_SENSORS.map(|(x, y)| {
+            let (x, y) = pose.transform(x, y);
+            !self
+                .map
+                .line
+                .windows(2)
+                .any(|s| segment_distance([x, y], s[0], s[1]) <= self.map.line_width / 2.)
+        })
+    }
+
+    /// Distance to the nearest wall the ultrasonic sensor points at, in meters
+    pub fn ultra_sensor(&self) -> f64 {
+        let (origin, dir) = self.ultra_ray();
+        self.map
+            .wall_segments()
+            .filter_map(|(a, b)| ray_hit(origin, dir, a, b))
+            .fold(ULTRA_RANGE, f64::min)
+    }
+
+    /// Where the ultrasonic sensor is and the direction it points in
+    pub fn ultra_ray(&self) -> ([f64; 2], [f64; 2]) {
+        let pose = self.pose();
+        let (x, y) = pose.transform(ULTRA_MOUNT.0, ULTRA_MOUNT.1);
+        let a = pose.heading + self.servo.to_radians();
+        ([x, y], [a.cos(), a.sin()])
+    }
+}
+
+fn segment_distance(p: [f64; 2], a: [f64; 2], b: [f64; 2]) -> f64 {
+    let (ab, ap) = ([b[0] - a[0], b[1] - a[1]], [p[0] - a[0], p[1] - a[1]]);
+    let len = ab[0] * ab[0] + ab[1] * ab[1];
+    let t = if len == 0. {
+        0.
+    } else {
+        ((ap[0] * ab[0] + ap[1] * ab[1]) / len).clamp(0., 1.)
+    };
+    let (dx, dy) = (ap[0] - ab[0] * t, ap[1] - ab[1] * t);
+    (dx * dx + dy * dy).sqrt()
+}
+
+/// How far along the ray `dir` (a unit vector) from `origin` it crosses the segment `a`-`b`
+fn ray_hit(origin: [f64; 2], dir: [f64; 2], a: [f64; 2], b: [f64; 2]) -> Option<f64> {
+    let ab = [b[0] - a[0], b[1] - a[1]];
+    let denom = dir[0] * ab[1] - dir[1] * ab[0];
+    if denom.abs() < 1e-12 {
+        return None;
+    }
+    let ao = [a[0] - origin[0], a[1] - origin[1]];
+    let t = (ao[0] * ab[1] - ao[1] * ab[0]) / denom;
+    let u = (ao[0] * dir[1] - ao[1] * dir[0]) / denom;
+    (t >= 0. && (0. ..=1.).contains(&u)).then_some(t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn world() -> World {
+        World::new(Map::default(), Calibration::default())
+    }
+
+    #[test]
+    fn track_sensors() {
+        let map = Map {
+            line: vec![[0., 1.], [3., 1.]],
+            start: [1., 1., 0.],
+            ..Map::default()
+        };
+        let mut w = World::new(map, Calibration::default());
+        // the middle two straddle the line
+        assert_eq!(w.track_sensors(), [true, false, false, true]);
+        // drifted to the left of it
+        w.odometry.reset(Pose::new(1., 1.02, 0.));
+        assert_eq!(w.track_sensors(), [true, true, true, false]);
+        w.odometry.reset(Pose::new(1., 1.5, 0.));
+        assert_eq!(w.track_sensors(), [true; 4]);
+    }
+
+    #[test]
+    fn starts_on_the_line() {
+        let w = world();
+        assert!(w.track_sensors().contains(&false));
+    }
+
+    #[test]
+    fn ultra_sees_walls() {
+        let mut w = world();
+        w.odometry.reset(Pose::new(1., 1., 0.));
+        assert!((w.ultra_sensor() - (3. - 1. - ULTRA_MOUNT.0)).abs() < 1e-9);
+        // looking left at the wall 1m away
+        w.servo = 90.;
+        assert!((w.ultra_sensor() - 1.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn stops_at_walls() {
+        let mut w = world();
+        w.odometry.reset(Pose::new(2.87, 1., 0.));
+        w.odometry.drive(1., 1.);
+        w.step(0.1);
+        assert_eq!(w.pose().x, 2.87);
+        w.odometry.drive(-1., -1.);
+        w.step(0.1);
+        assert!(w.pose().x < 2.87);
+    }
+}
@@ -0,0 +1,120 @@
+use crate::{gamepad::GamepadConfig, keymap::KeyConfig, odometry::OdometryConfig};
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use std::{collections::HashMap, path::PathBuf};
+
+pub const DEFAULT_HOST: &str = "10.0.0.236";
+pub const DEFAULT_PORT: u16 = 1110;
+
+/// Contents of `~/.config/roblib-tui/config.toml`
+///
+/// ```toml
+/// default = "roland"
+///
+/// [robots.roland]
+/// host = "10.0.0.236"
+///
+/// [robots.roland2]
+/// host = "10.0.0.112"
+/// port = 1110
+///
+/// [[gpio]]
+/// pin = 17
+/// mode = "output"
+/// name = "lamp"
+/// ```
+///
+/// Key bindings go in a `[keys]` table, see [`KeyConfig`], the gamepad's settings in a
+/// `[gamepad]` table, see [`GamepadConfig`], and the robot's measurements in an `[odometry]`
+/// table, see [`OdometryConfig`].
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// profile used when no `--robot` is given
+    pub default: Option<String>,
+    pub robots: HashMap<String, Profile>,
+    /// pins shown on the GPIO tab
+    pub gpio: Vec<PinConfig>,
+    pub keys: KeyConfig,
+    pub gamepad: GamepadConfig,
+    pub odometry: OdometryConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Profile {
+    pub host: String,
+    pub port: Option<u16>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PinConfig {
+    pub pin: u8,
+    pub mode: PinKind,
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PinKind {
+    Input,
+    Output,
+    Pwm,
+    Servo,
+}
+
+/// A file in our data directory, which is created if needed
+pub fn data_file(name: &str) -> Option<PathBuf> {
+    let dir = dirs::data_dir()?.join("roblib-tui");
+    std::fs::create_dir_all(&dir).ok()?;
+    Some(dir.join(name))
+}
+
+impl Config {
+    pub fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|d| d.join("roblib-tui").join("config.toml"))
+    }
+
+    /// Load the config file, a missing file is not an error
+    pub fn load() -> Result<Self> {
+        let Some(path) = Self::path() else {
+            return Ok(Self::default());
+        };
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let txt = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+        toml::from_str(&txt)
+            .with_context(|| format!("Failed to parse config file {}", path.display()))
+    }
+
+    /// Figure out which address to connect to.
+    ///
+    /// `host` and `port` come from the cli (or their env vars) and take precedence over the
+    /// selected profile, which falls back to the `default` one.
+    pub fn addr(
+        &self,
+        robot: Option<&str>,
+        host: Option<&str>,
+        port: Option<u16>,
+    ) -> Result<String> {
+        let profile = match robot.or(self.default.as_deref()) {
+            Some(name) => Some(
+                self.robots
+                    .get(name)
+                    .ok_or_else(|| anyhow!("Unknown robot profile: {name}"))?,
+            ),
+            None => None,
+        };
+
+        let host = host
+            .or(profile.map(|p| p.host.as_str()))
+            .unwrap_or(DEFAULT_HOST);
+        let port = port
+            .or(profile.and_then(|p| p.port))
+            .unwrap_or(DEFAULT_PORT);
+
+        Ok(format!("{host}:{port}"))
+    }
+}
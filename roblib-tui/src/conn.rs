@@ -0,0 +1,146 @@
+use crate::render::{Msg, Robot, Tx};
+use anyhow::{anyhow, Result};
+use roblib_client::{
+    roblib::cmd,
+    transports::{tcp::TcpAsync, TransportAsync},
+    RobotAsync,
+};
+use std::{
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant},
+};
+use tokio::sync::Notify;
+use tokio_util::sync::CancellationToken;
+
+const PING_INTERVAL: Duration = Duration::from_secs(1);
+const PING_TIMEOUT: Duration = Duration::from_secs(2);
+const MIN_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(8);
+/// failed attempts before the robot is shown as offline, retrying doesn't stop
+const OFFLINE_AFTER: u32 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Status {
+    /// with the round trip time of the last ping
+    Connected(Option<Duration>),
+    /// with the number of the failed attempt
+    Reconnecting(u32),
+    Offline,
+    /// playing back a recording, there's no robot
+    Replay,
+}
+
+/// The connection to the robot, which gets replaced when it drops.
+pub struct Conn {
+    addr: String,
+    robot: RwLock<Option<Arc<Robot>>>,
+    status: Mutex<Status>,
+    lost: Notify,
+}
+
+impl Conn {
+    pub async fn connect(addr: String) -> Result<Arc<Self>> {
+        let robot = RobotAsync::new(TcpAsync::connect(&addr).await?);
+        Ok(Arc::new(Self {
+            addr,
+            robot: RwLock::new(Some(Arc::new(robot))),
+            status: Mutex::new(Status::Connected(None)),
+            lost: Notify::new(),
+        }))
+    }
+
+    /// A connection that's never made, for replaying recordings
+    pub fn offline() -> Arc<Self> {
+        Arc::new(Self {
+            addr: String::new(),
+            robot: RwLock::new(None),
+            status: Mutex::new(Status::Replay),
+            lost: Notify::new(),
+        })
+    }
+
+    /// `None` while disconnected
+    pub fn robot(&self) -> Option<Arc<Robot>> {
+        self.robot.read().unwrap().clone()
+    }
+
+    pub fn status(&self) -> Status {
+        *self.status.lock().unwrap()
+    }
+
+    /// Drop the current connection and start reconnecting
+    pub fn lost(&self, err: impl Into<anyhow::Error>) {
+        if self.robot.write().unwrap().take().is_some() {
+            log::error!("Connection lost: {}", err.into());
+            *self.status.lock().unwrap() = Status::Reconnecting(0);
+            self.lost.notify_one();
+        }
+    }
+
+    /// Unwrap the result of a robot command, treating errors as a dropped connection
+    pub fn check<T, E: Into<anyhow::Error>>(&self, res: Result<T, E>) -> Option<T> {
+        match res {
+            Ok(v) => Some(v),
+            Err(e) => {
+                self.lost(e);
+                None
+            }
+        }
+    }
+}
+
+/// Ping the robot while connected and reconnect with backoff when it drops.
+///
+/// Sends [`Msg::Reconnected`] after a new connection is made, so subscriptions can be restored.
+pub async fn run(conn: Arc<Conn>, tx: Tx, cancel: CancellationToken) -> Result<()> {
+    let _cancel = cancel.clone().drop_guard();
+    let mut backoff = MIN_BACKOFF;
+    let mut attempt = 0;
+
+    loop {
+        let wait = match conn.robot() {
+            Some(_) => PING_INTERVAL,
+            None => backoff,
+        };
+        tokio::select! {
+            _ = cancel.cancelled() => return Ok(()),
+            // retry right away
+            _ = conn.lost.notified() => (),
+            _ = tokio::time::sleep(wait) => (),
+        }
+
+        if let Some(robot) = conn.robot() {
+            let start = Instant::now();
+            match tokio::time::timeout(PING_TIMEOUT, robot.transport.cmd(cmd::GetUptime)).await {
+                Ok(Ok(_)) => {
+                    *conn.status.lock().unwrap() = Status::Connected(Some(start.elapsed()))
+                }
+                Ok(Err(e)) => conn.lost(e),
+                Err(_) => conn.lost(anyhow!("Ping timed out")),
+            }
+            continue;
+        }
+
+        attempt += 1;
+        match TcpAsync::connect(&conn.addr).await {
+            Ok(t) => {
+                log::info!("Reconnected to {}", conn.addr);
+                *conn.robot.write().unwrap() = Some(Arc::new(RobotAsync::new(t)));
+                *conn.status.lock().unwrap() = Status::Connected(None);
+                attempt = 0;
+                backoff = MIN_BACKOFF;
+                // nobody to tell if the UI is already gone
+                let _ = tx.send(Msg::Reconnected);
+            }
+            Err(e) => {
+                log::warn!("Reconnect attempt {attempt} failed: {e}");
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+                *conn.status.lock().unwrap() = if attempt >= OFFLINE_AFTER {
+                    Status::Offline
+                } else {
+                    Status::Reconnecting(attempt)
+                };
+            }
+        }
+    }
+}
@@ -0,0 +1,202 @@
+use crate::{render::Robot, subs::Subs};
+use anyhow::{anyhow, bail, Result};
+use drive::{roland, Odometry, Pose};
+use roblib_client::{
+    roblib::cmd::{self, Command, Concrete},
+    transports::TransportAsync,
+};
+use serde::Serialize;
+use serde_json::Value;
+use std::time::Duration;
+
+/// Calls `$m!(<args> [<every Concrete variant that's sent as-is, with its arguments>])`.
+///
+/// This is the only list of commands to touch when roblib gets a new one, the dispatcher and
+/// [`COMMANDS`] are generated from it. roblib can't enumerate its commands for us, but the
+/// generated match has no catch-all, so a new `Concrete` variant fails to build until it's added
+/// here instead of silently not working. `GetPosition` is left out, it isn't in `Concrete` with
+/// our features, `:pose` tells where the odometry thinks the robot is instead.
+macro_rules! with_commands {
+    ($m:ident!($($args:tt)*)) => {
+        $m!($($args)* [
+            MoveRobot(left, right),
+            MoveRobotByAngle(angle, speed),
+            StopRobot,
+            Led(red, green, blue),
+            RolandServo(degree),
+            Buzzer(pulse_width),
+            TrackSensor,
+            UltraSensor,
+            PinMode(pin, mode),
+            ReadPin(pin),
+            WritePin(pin, level),
+            Pwm(pin, hz, cycle),
+            Servo(pin, degree),
+            Nop,
+            GetUptime,
+            Abort,
+        ])
+    };
+}
+
+macro_rules! names {
+    ([$($extra:tt)*] [$($list:tt)*]) => {
+        names!($($extra)*, $($list)*)
+    };
+    ($($variant:ident $(($($arg:ident),*))?),* $(,)?) => {
+        &[$((
+            stringify!($variant),
+            <cmd::$variant as Command>::PREFIX,
+            &[$($(stringify!($arg)),*)?],
+        )),*]
+    };
+}
+
+/// The name, text format prefix and argument names of every command
+pub static COMMANDS: &[(&str, char, &[&str])] =
+    with_commands!(names!([Subscribe(event), Unsubscribe(event)]));
+
+macro_rules! dispatch {
+    ($cmd:expr, $robot:expr, [$($variant:ident $(($($arg:ident),*))?),* $(,)?]) => {
+        match $cmd {
+            $(Concrete::$variant(c) => run(c, $robot).await,)*
+            // these need somewhere to send the values, see `subs`
+            Concrete::Subscribe(_) | Concrete::Unsubscribe(_) => {
+                bail!("Subscriptions can't be executed directly")
+            }
+        }
+    };
+}
+
+/// Run a line typed by the user: a text format command, or a command for the terminal itself
+/// starting with a `:`. Returns what to show the user.
+pub async fn eval(
+    line: &str,
+    robot: Option<&Robot>,
+    subs: &mut Subs,
+    odometry: &mut Odometry,
+) -> Result<Option<String>> {
+    if let Some(local) = line.strip_prefix(':') {
+        return local_cmd(local, robot, subs, odometry).await;
+    }
+
+    let cmd: Concrete = roblib_client::roblib::text_format::de::from_str(line)?;
+    let robot = robot.ok_or_else(|| anyhow!("Not connected"))?;
+    match cmd {
+        Concrete::Subscribe(c) => {
+            let id = subs.subscribe(robot, c.0).await?;
+            Ok(Some(format!("subscribed, id {id}")))
+        }
+        Concrete::Unsubscribe(c) => {
+            let id = subs
+                .find(&c.0)
+                .ok_or_else(|| anyhow!("Not subscribed to {:?}", c.0))?;
+            subs.unsubscribe(Some(robot), id).await?;
+            Ok(Some(format!("unsubscribed {id}")))
+        }
+        cmd => {
+            let wheels = drives(&cmd);
+            let ret = execute(cmd, &robot.transport).await?;
+            if let Some((left, right)) = wheels {
+                odometry.drive(left, right);
+            }
+            Ok(ret.as_ref().map(show))
+        }
+    }
+}
+
+async fn local_cmd(
+    line: &str,
+    robot: Option<&Robot>,
+    subs: &mut Subs,
+    odometry: &Odometry,
+) -> Result<Option<String>> {
+    let mut args = line.split_whitespace();
+    match args.next() {
+        Some("pose") => Ok(Some(show_pose(odometry.pose()))),
+        Some("subs") if subs.list().is_empty() => Ok(Some("no subscriptions".into())),
+        Some("subs") => Ok(Some(
+            subs.list()
+                .iter()
+                .map(|s| format!("#{} {:?}", s.id, s.event))
+                .collect::<Vec<_>>()
+                .join(", "),
+        )),
+        Some("unsub") => {
+            let id = args
+                .next()
+                .ok_or_else(|| anyhow!("Usage: :unsub <id>"))?
+                .parse()?;
+            subs.unsubscribe(robot, id).await?;
+            Ok(None)
+        }
+        Some(c) => bail!("Unknown command: :{c}"),
+        None => bail!("Missing command after ':'"),
+    }
+}
+
+/// Run any command, returning its return value unless it's `()`
+pub async fn execute(cmd: Concrete, robot: &impl TransportAsync) -> Result<Option<Value>> {
+    with_commands!(dispatch!(cmd, robot,))
+}
+
+async fn run<C>(cmd: C, robot: &impl TransportAsync) -> Result<Option<Value>>
+where
+    C: Command,
+    C::Return: Serialize,
+{
+    let ret = robot.cmd(cmd).await?;
+    Ok(match serde_json::to_value(ret)? {
+        Value::Null => None,
+        v => Some(durations(v)),
+    })
+}
+
+/// The wheel speeds a command drives at, `None` if it leaves them alone. Whatever sends
+/// commands tells its [`Odometry`] about them through this.
+pub fn drives(cmd: &Concrete) -> Option<(f64, f64)> {
+    match cmd {
+        Concrete::MoveRobot(cmd::MoveRobot(left, right)) => Some((*left, *right)),
+        Concrete::MoveRobotByAngle(cmd::MoveRobotByAngle(angle, speed)) => {
+            Some(roland::by_angle(*angle, *speed))
+        }
+        Concrete::StopRobot(_) | Concrete::Abort(_) => Some((0., 0.)),
+        _ => None,
+    }
+}
+
+/// A pose for people to read, in meters and degrees
+pub fn show_pose(pose: Pose) -> String {
+    format!(
+        "x {:.2}m y {:.2}m {:.0}°",
+        pose.x,
+        pose.y,
+        pose.heading.to_degrees()
+    )
+}
+
+/// A return value for people to read, strings without quotes
+pub fn show(v: &Value) -> String {
+    match v {
+        Value::String(s) => s.clone(),
+        v => v.to_string(),
+    }
+}
+
+/// Replace the `{"secs", "nanos"}` objects serde makes of a `Duration` with something readable
+fn durations(v: Value) -> Value {
+    match v {
+        Value::Object(o) => {
+            let secs = o.get("secs").and_then(Value::as_u64);
+            let nanos = o.get("nanos").and_then(Value::as_u64);
+            match (secs, nanos) {
+                (Some(secs), Some(nanos)) if o.len() == 2 => {
+                    Value::String(format!("{:?}", Duration::new(secs, nanos as u32)))
+                }
+                _ => Value::Object(o.into_iter().map(|(k, v)| (k, durations(v))).collect()),
+            }
+        }
+        Value::Array(a) => Value::Array(a.into_iter().map(durations).collect()),
+        v => v,
+    }
+}
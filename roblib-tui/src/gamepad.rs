@@ -0,0 +1,679 @@
+//! Driving with a joystick through the Linux joystick API (`/dev/input/js*`) or the event
+//! interface (`/dev/input/event*`).
+//!
+//! A regular file is treated as a recording of the device, made with something like
+//! `cat /dev/input/js0 > drive.js` or `cat /dev/input/event5 > drive.evdev`, and is replayed
+//! with its original timing. Recordings ending in `.evdev` are read as evdev events.
+
+use crate::{
+    config,
+    keymap::Action,
+    render::{Msg, Tx},
+};
+use anyhow::{bail, Context, Result};
+use drive::{DriveMixer, Mode};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufRead, ErrorKind, Read},
+    os::unix::fs::FileTypeExt,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+const JS_EVENT_BUTTON: u8 = 0x01;
+const JS_EVENT_AXIS: u8 = 0x02;
+/// set on the events describing the initial state, sent when the device is opened
+const JS_EVENT_INIT: u8 = 0x80;
+const AXIS_MAX: i16 = i16::MAX;
+
+const EV_KEY: u16 = 0x01;
+const EV_ABS: u16 = 0x03;
+/// `BTN_TRIGGER`, the first of a joystick's buttons
+const BTN_JOYSTICK: u16 = 0x120;
+/// `BTN_SOUTH`, the first of a gamepad's buttons
+const BTN_GAMEPAD: u16 = 0x130;
+/// the last axis code
+const ABS_MAX: u16 = 0x3f;
+
+/// How often the stick position is re-sent while it's deflected, like a held key repeats
+const REPEAT: Duration = Duration::from_millis(100);
+const CALIBRATION_FILE: &str = "gamepad.toml";
+
+/// What the gamepad tells the UI
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pad {
+    /// wheel speeds from the sticks, before the speed setting is applied, `None` when centered
+    Drive(Option<(f64, f64)>),
+    /// a button or trigger that's bound to an action was pressed
+    Action(Action),
+}
+
+/// The `[gamepad]` table of the config file.
+///
+/// ```toml
+/// [gamepad]
+/// device = "/dev/input/js0"
+/// mode = "arcade"
+/// deadzone = 0.1
+/// axes = [0, 1]
+///
+/// [gamepad.buttons]
+/// stop = 0
+/// buzzer = 1
+///
+/// [gamepad.triggers]
+/// speed_up = 5
+/// speed_down = 2
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct GamepadConfig {
+    pub device: Option<PathBuf>,
+    pub mode: DriveMode,
+    /// stick deflection ignored around the center, `0..1`
+    pub deadzone: f64,
+    /// the turn and throttle axes, or the left and right wheel axes in tank mode
+    pub axes: Option<[u8; 2]>,
+    pub buttons: HashMap<Action, u8>,
+    /// analog triggers, they fire once when pulled halfway
+    pub triggers: HashMap<Action, u8>,
+}
+
+impl Default for GamepadConfig {
+    fn default() -> Self {
+        Self {
+            device: None,
+            mode: DriveMode::Arcade,
+            deadzone: 0.1,
+            axes: None,
+            buttons: HashMap::from([(Action::Stop, 0), (Action::Buzzer, 1)]),
+            triggers: HashMap::from([(Action::SpeedUp, 5), (Action::SpeedDown, 2)]),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DriveMode {
+    Tank,
+    Arcade,
+    Curvature,
+}
+
+impl From<DriveMode> for Mode {
+    fn from(m: DriveMode) -> Self {
+        match m {
+            DriveMode::Tank => Mode::Tank,
+            DriveMode::Arcade => Mode::Arcade,
+            DriveMode::Curvature => Mode::Curvature,
+        }
+    }
+}
+
+/// How the events of a device or recording are laid out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    /// the joystick API's 8 byte `js_event`
+    Js,
+    /// the event interface's `input_event`, 24 bytes on 64 bit systems
+    Evdev,
+}
+
+impl Format {
+    /// `/dev/input/event*` devices and recordings ending in `.evdev` are evdev, the rest js
+    fn of(path: &Path) -> Self {
+        let name = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or_default();
+        let ext = path.extension().and_then(|e| e.to_str());
+        if name.starts_with("event") || ext == Some("evdev") {
+            Format::Evdev
+        } else {
+            Format::Js
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Format::Js => 8,
+            Format::Evdev => 24,
+        }
+    }
+
+    /// Read an event of `self.size()` bytes, evdev events that mean nothing to a joystick are
+    /// `None`
+    fn parse(self, b: &[u8]) -> Option<JsEvent> {
+        match self {
+            Format::Js => Some(JsEvent {
+                time: u32::from_ne_bytes([b[0], b[1], b[2], b[3]]),
+                value: i16::from_ne_bytes([b[4], b[5]]),
+                kind: b[6],
+                number: b[7],
+            }),
+            Format::Evdev => {
+                let secs = i64::from_ne_bytes(b[0..8].try_into().unwrap());
+                let micros = i64::from_ne_bytes(b[8..16].try_into().unwrap());
+                let kind = u16::from_ne_bytes([b[16], b[17]]);
+                let code = u16::from_ne_bytes([b[18], b[19]]);
+                let value = i32::from_ne_bytes(b[20..24].try_into().unwrap());
+
+                let (kind, number) = match (kind, code) {
+                    // 2 is a key repeating
+                    (EV_KEY, _) if value > 1 => return None,
+                    (EV_KEY, BTN_GAMEPAD..) => (JS_EVENT_BUTTON, code - BTN_GAMEPAD),
+                    (EV_KEY, BTN_JOYSTICK..) => (JS_EVENT_BUTTON, code - BTN_JOYSTICK),
+                    (EV_ABS, ..=ABS_MAX) => (JS_EVENT_AXIS, code),
+                    _ => return None,
+                };
+                Some(JsEvent {
+                    time: (secs * 1000 + micros / 1000) as u32,
+                    value: value.clamp(-AXIS_MAX as i32, AXIS_MAX as i32) as i16,
+                    kind,
+                    number: u8::try_from(number).ok()?,
+                })
+            }
+        }
+    }
+}
+
+/// An event as the joystick API reports it, evdev events are turned into these.
+///
+/// evdev buttons are numbered from `BTN_SOUTH`, or `BTN_TRIGGER` for joysticks, so they can be
+/// numbered differently than by the joystick API. Axes are the `ABS_*` codes, which match it
+/// for most gamepads.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct JsEvent {
+    /// milliseconds, from an arbitrary start
+    time: u32,
+    value: i16,
+    kind: u8,
+    number: u8,
+}
+
+/// The range an axis actually moves in, sticks rarely rest at exactly 0 or reach the ends
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AxisCalibration {
+    pub axis: u8,
+    pub min: i16,
+    pub center: i16,
+    pub max: i16,
+}
+
+impl Default for AxisCalibration {
+    fn default() -> Self {
+        Self {
+            axis: 0,
+            min: -AXIS_MAX,
+            center: 0,
+            max: AXIS_MAX,
+        }
+    }
+}
+
+impl AxisCalibration {
+    /// Scale a raw value to `-1..=1`
+    fn normalize(&self, v: i16) -> f64 {
+        let (v, center) = (v as f64, self.center as f64);
+        let range = if v >= center {
+            self.max as f64 - center
+        } else {
+            center - self.min as f64
+        };
+        if range <= 0. {
+            return 0.;
+        }
+        ((v - center) / range).clamp(-1., 1.)
+    }
+
+    /// Halfway between the ends
+    fn midpoint(&self) -> i16 {
+        ((self.min as i32 + self.max as i32) / 2) as i16
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Calibration {
+    axis: Vec<AxisCalibration>,
+}
+
+impl Calibration {
+    fn load() -> Result<Self> {
+        let Some(path) = config::data_file(CALIBRATION_FILE) else {
+            return Ok(Self::default());
+        };
+        match std::fs::read_to_string(&path) {
+            Ok(txt) => {
+                toml::from_str(&txt).with_context(|| format!("Failed to parse {}", path.display()))
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        }
+    }
+
+    fn get(&self, axis: u8) -> AxisCalibration {
+        self.axis
+            .iter()
+            .find(|c| c.axis == axis)
+            .copied()
+            .unwrap_or(AxisCalibration {
+                axis,
+                ..Default::default()
+            })
+    }
+}
+
+/// An opened joystick or recording, ready to be read by [`spawn`](Self::spawn)
+pub struct Gamepad {
+    file: File,
+    path: PathBuf,
+    format: Format,
+    recording: bool,
+    mixer: DriveMixer,
+    axes: [AxisCalibration; 2],
+    /// y axes are negative when pushed forward
+    invert: [bool; 2],
+    buttons: HashMap<u8, Action>,
+    /// with the value past which each counts as pulled
+    triggers: HashMap<u8, (Action, i16)>,
+}
+
+impl Gamepad {
+    pub fn open(path: &Path, cfg: &GamepadConfig) -> Result<Self> {
+        let file =
+            File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        let recording = !file.metadata()?.file_type().is_char_device();
+
+        let mode = Mode::from(cfg.mode);
+        let axes = cfg.axes.unwrap_or(match mode {
+            // both sticks' y axes
+            Mode::Tank => [1, 4],
+            // the left stick
+            _ => [0, 1],
+        });
+        let invert = match mode {
+            Mode::Tank => [true, true],
+            _ => [false, true],
+        };
+        let calibration = Calibration::load()?;
+
+        Ok(Self {
+            file,
+            path: path.to_owned(),
+            format: Format::of(path),
+            recording,
+            mixer: DriveMixer {
+                deadzone: cfg.deadzone,
+                ..DriveMixer::new(mode)
+            },
+            axes: axes.map(|a| calibration.get(a)),
+            invert,
+            buttons: cfg.buttons.iter().map(|(a, b)| (*b, *a)).collect(),
+            triggers: cfg
+                .triggers
+                .iter()
+                .map(|(a, t)| (*t, (*a, calibration.get(*t).midpoint())))
+                .collect(),
+        })
+    }
+
+    /// Read the gamepad and send [`Msg::Gamepad`]s until `cancel`
+    pub fn spawn(self, tx: Tx, cancel: CancellationToken) {
+        let (events_tx, events) = mpsc::unbounded_channel();
+        let (file, format, recording) = (self.file.try_clone(), self.format, self.recording);
+        match file {
+            // the read blocks, and a thread that's stuck in it can't hold up the exit
+            Ok(file) => {
+                std::thread::spawn(move || {
+                    if let Err(e) = read_events(file, format, recording, events_tx) {
+                        log::error!("Gamepad read failed: {e}");
+                    }
+                });
+            }
+            Err(e) => log::error!("Failed to read {}: {e}", self.path.display()),
+        }
+        tokio::spawn(self.run(events, tx, cancel));
+    }
+
+    async fn run(
+        self,
+        mut events: mpsc::UnboundedReceiver<JsEvent>,
+        tx: Tx,
+        cancel: CancellationToken,
+    ) {
+        let mut raw = [self.axes[0].center, self.axes[1].center];
+        let mut pulled: HashMap<u8, bool> = HashMap::new();
+        let mut sent = None;
+        let mut repeat = tokio::time::interval(REPEAT);
+
+        loop {
+            let ev = tokio::select! {
+                _ = cancel.cancelled() => return,
+                ev = events.recv() => ev,
+                _ = repeat.tick() => {
+                    if sent.is_some() && tx.send(Msg::Gamepad(Pad::Drive(sent))).is_err() {
+                        return;
+                    }
+                    continue;
+                }
+            };
+            let Some(ev) = ev else {
+                log::info!("Gamepad {} ended", self.path.display());
+                let _ = tx.send(Msg::Gamepad(Pad::Drive(None)));
+                return;
+            };
+
+            let init = ev.kind & JS_EVENT_INIT != 0;
+            let mut action = None;
+            match ev.kind & !JS_EVENT_INIT {
+                JS_EVENT_BUTTON if !init && ev.value == 1 => {
+                    action = self.buttons.get(&ev.number).copied();
+                }
+                JS_EVENT_AXIS => {
+                    for (i, axis) in self.axes.iter().enumerate() {
+                        if axis.axis == ev.number {
+                            raw[i] = ev.value;
+                        }
+                    }
+                    if let Some((a, midpoint)) = self.triggers.get(&ev.number) {
+                        // released triggers sit at one end
+                        let now = ev.value > *midpoint;
+                        let was = pulled.insert(ev.number, now).unwrap_or(false);
+                        if now && !was && !init {
+                            action = Some(*a);
+                        }
+                    }
+                }
+                _ => (),
+            }
+
+            if let Some(a) = action {
+                if tx.send(Msg::Gamepad(Pad::Action(a))).is_err() {
+                    return;
+                }
+            }
+            let drive = self.drive(raw);
+            if drive != sent {
+                sent = drive;
+                if tx.send(Msg::Gamepad(Pad::Drive(drive))).is_err() {
+                    return;
+                }
+            }
+        }
+    }
+
+    fn drive(&self, raw: [i16; 2]) -> Option<(f64, f64)> {
+        let [x, y] = [0, 1].map(|i| {
+            let v = self.axes[i].normalize(raw[i]);
+            if self.invert[i] {
+                -v
+            } else {
+                v
+            }
+        });
+        match self.mixer.mix(x, y) {
+            (left, right) if left == 0. && right == 0. => None,
+            d => Some(d),
+        }
+    }
+}
+
+fn read_events(
+    mut file: File,
+    format: Format,
+    recording: bool,
+    tx: mpsc::UnboundedSender<JsEvent>,
+) -> Result<()> {
+    let mut last = None;
+    let mut buf = vec![0; format.size()];
+    loop {
+        match file.read_exact(&mut buf) {
+            Ok(()) => (),
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e.into()),
+        }
+        let Some(ev) = format.parse(&buf) else {
+            continue;
+        };
+        if recording {
+            if let Some(last) = last {
+                let wait = ev.time.saturating_sub(last);
+                std::thread::sleep(Duration::from_millis(wait as u64));
+            }
+            last = Some(ev.time);
+        }
+        if tx.send(ev).is_err() {
+            return Ok(());
+        }
+    }
+}
+
+/// Find the resting point and range of every axis, interactively, and save them
+pub fn calibrate(path: &Path) -> Result<()> {
+    let mut file =
+        File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    if !file.metadata()?.file_type().is_char_device() {
+        bail!("{} is not a joystick device", path.display());
+    }
+    let format = Format::of(path);
+
+    // axis -> (current, min, max)
+    let axes = Arc::new(Mutex::new(HashMap::<u8, (i16, i16, i16)>::new()));
+    {
+        let axes = axes.clone();
+        std::thread::spawn(move || {
+            let mut buf = vec![0; format.size()];
+            while file.read_exact(&mut buf).is_ok() {
+                let Some(ev) = format.parse(&buf) else {
+                    continue;
+                };
+                if ev.kind & !JS_EVENT_INIT != JS_EVENT_AXIS {
+                    continue;
+                }
+                let mut axes = axes.lock().unwrap();
+                let (cur, min, max) = axes.entry(ev.number).or_insert((0, 0, 0));
+                *cur = ev.value;
+                *min = (*min).min(ev.value);
+                *max = (*max).max(ev.value);
+            }
+        });
+    }
+
+    let wait_enter = || -> Result<()> {
+        let mut line = String::new();
+        std::io::stdin().lock().read_line(&mut line)?;
+        Ok(())
+    };
+
+    // evdev only says where a stick is once it moves
+    println!("Wiggle the sticks, let go of them and the triggers, then press Enter");
+    wait_enter()?;
+    let centers: HashMap<u8, i16> = axes
+        .lock()
+        .unwrap()
+        .iter_mut()
+        .map(|(axis, (cur, min, max))| {
+            // only count the range from here on
+            (*min, *max) = (*cur, *cur);
+            (*axis, *cur)
+        })
+        .collect();
+
+    println!("Move every stick and trigger all the way in every direction, then press Enter");
+    wait_enter()?;
+    let mut axis: Vec<_> = axes
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(axis, (_, min, max))| AxisCalibration {
+            axis: *axis,
+            min: *min,
+            center: centers.get(axis).copied().unwrap_or_default(),
+            max: *max,
+        })
+        .collect();
+    axis.sort_by_key(|c| c.axis);
+
+    for c in &axis {
+        println!(
+            "axis {:>2}: {:>6} .. {:>6} .. {:>6}",
+            c.axis, c.min, c.center, c.max
+        );
+    }
+    let path = config::data_file(CALIBRATION_FILE)
+        .context("No data directory to save the calibration in")?;
+    std::fs::write(&path, toml::to_string(&Calibration { axis })?)?;
+    println!("Saved to {}", path.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn js(time: u32, value: i16, kind: u8, number: u8) -> Vec<u8> {
+        let mut b = time.to_ne_bytes().to_vec();
+        b.extend(value.to_ne_bytes());
+        b.extend([kind, number]);
+        b
+    }
+
+    fn evdev(secs: i64, micros: i64, kind: u16, code: u16, value: i32) -> Vec<u8> {
+        let mut b = secs.to_ne_bytes().to_vec();
+        b.extend(micros.to_ne_bytes());
+        b.extend(kind.to_ne_bytes());
+        b.extend(code.to_ne_bytes());
+        b.extend(value.to_ne_bytes());
+        b
+    }
+
+    fn pad(mode: Mode, deadzone: f64) -> Gamepad {
+        let invert = match mode {
+            Mode::Tank => [true, true],
+            _ => [false, true],
+        };
+        Gamepad {
+            file: File::open("/dev/null").unwrap(),
+            path: PathBuf::new(),
+            format: Format::Js,
+            recording: true,
+            mixer: DriveMixer {
+                deadzone,
+                ..DriveMixer::new(mode)
+            },
+            axes: [AxisCalibration::default(); 2],
+            invert,
+            buttons: HashMap::new(),
+            triggers: HashMap::new(),
+        }
+    }
+
+    fn close((l1, r1): (f64, f64), (l2, r2): (f64, f64)) -> bool {
+        (l1 - l2).abs() < 1e-3 && (r1 - r2).abs() < 1e-3
+    }
+
+    #[test]
+    fn format_of() {
+        assert_eq!(Format::of(Path::new("/dev/input/js0")), Format::Js);
+        assert_eq!(Format::of(Path::new("/dev/input/event5")), Format::Evdev);
+        assert_eq!(Format::of(Path::new("drive.evdev")), Format::Evdev);
+        assert_eq!(Format::of(Path::new("drive.js")), Format::Js);
+    }
+
+    #[test]
+    fn parse_js() {
+        let b = js(1234, -300, JS_EVENT_AXIS | JS_EVENT_INIT, 4);
+        let ev = Format::Js.parse(&b).unwrap();
+        assert_eq!(
+            ev,
+            JsEvent {
+                time: 1234,
+                value: -300,
+                kind: JS_EVENT_AXIS | JS_EVENT_INIT,
+                number: 4,
+            }
+        );
+    }
+
+    #[test]
+    fn parse_evdev() {
+        let ev = Format::Evdev
+            .parse(&evdev(2, 500_000, EV_ABS, 1, 40_000))
+            .unwrap();
+        assert_eq!(ev.time, 2500);
+        assert_eq!((ev.kind, ev.number, ev.value), (JS_EVENT_AXIS, 1, AXIS_MAX));
+
+        let ev = Format::Evdev.parse(&evdev(0, 0, EV_KEY, 0x131, 1)).unwrap();
+        assert_eq!((ev.kind, ev.number, ev.value), (JS_EVENT_BUTTON, 1, 1));
+        let ev = Format::Evdev.parse(&evdev(0, 0, EV_KEY, 0x122, 0)).unwrap();
+        assert_eq!((ev.kind, ev.number, ev.value), (JS_EVENT_BUTTON, 2, 0));
+
+        // key repeats, keyboard keys and sync events
+        assert_eq!(Format::Evdev.parse(&evdev(0, 0, EV_KEY, 0x130, 2)), None);
+        assert_eq!(Format::Evdev.parse(&evdev(0, 0, EV_KEY, 30, 1)), None);
+        assert_eq!(Format::Evdev.parse(&evdev(0, 0, 0, 0, 0)), None);
+    }
+
+    #[test]
+    fn normalize() {
+        let cal = AxisCalibration {
+            axis: 0,
+            min: 0,
+            center: 100,
+            max: 300,
+        };
+        assert_eq!(cal.normalize(100), 0.);
+        assert_eq!(cal.normalize(0), -1.);
+        assert_eq!(cal.normalize(50), -0.5);
+        assert_eq!(cal.normalize(200), 0.5);
+        // past what was seen while calibrating
+        assert_eq!(cal.normalize(1000), 1.);
+        assert_eq!(cal.midpoint(), 150);
+    }
+
+    #[test]
+    fn normalize_degenerate() {
+        // a trigger resting at its end only goes one way
+        let cal = AxisCalibration {
+            axis: 2,
+            min: -100,
+            center: -100,
+            max: 100,
+        };
+        assert_eq!(cal.normalize(-200), 0.);
+        assert_eq!(cal.normalize(100), 1.);
+        // never moved, or saved backwards
+        let cal = AxisCalibration {
+            axis: 2,
+            min: 50,
+            center: 0,
+            max: -50,
+        };
+        assert_eq!(cal.normalize(30), 0.);
+        assert_eq!(cal.normalize(-30), 0.);
+    }
+
+    #[test]
+    fn drive_arcade() {
+        let pad = pad(Mode::Arcade, 0.1);
+        assert_eq!(pad.drive([0, 0]), None);
+        // forward is negative on the y axis
+        assert!(close(pad.drive([0, -AXIS_MAX]).unwrap(), (1., 1.)));
+        assert!(close(pad.drive([0, AXIS_MAX]).unwrap(), (-1., -1.)));
+        assert!(close(pad.drive([AXIS_MAX, 0]).unwrap(), (1., -1.)));
+        // inside the deadzone
+        assert_eq!(pad.drive([AXIS_MAX / 20, -AXIS_MAX / 20]), None);
+    }
+
+    #[test]
+    fn drive_tank() {
+        let pad = pad(Mode::Tank, 0.);
+        assert!(close(pad.drive([-AXIS_MAX, AXIS_MAX]).unwrap(), (1., -1.)));
+        assert!(close(pad.drive([-AXIS_MAX / 2, 0]).unwrap(), (0.5, 0.)));
+    }
+}
@@ -0,0 +1,116 @@
+use crate::{
+    config::{PinConfig, PinKind},
+    render::Robot,
+};
+use anyhow::Result;
+use roblib_client::roblib::gpio::{GpioAsync, Mode};
+
+const DEFAULT_HZ: f64 = 50.;
+const MAX_HZ: f64 = 10_000.;
+/// change of the PWM duty cycle per step
+const DUTY_STEP: f64 = 0.05;
+/// change of the servo angle per step, in degrees
+const ANGLE_STEP: f64 = 5.;
+
+/// A pin on the GPIO tab, with the state it's driven to
+#[derive(Debug, Clone)]
+pub struct Pin {
+    pub pin: u8,
+    pub name: String,
+    pub kind: PinKind,
+    /// last level read from an input
+    pub level: Option<bool>,
+    /// outputs are high, PWM is running
+    pub on: bool,
+    pub hz: f64,
+    /// PWM duty cycle, `0..=1`
+    pub duty: f64,
+    /// servo angle in degrees, `-90..=90`
+    pub angle: f64,
+}
+
+impl Pin {
+    pub fn new(cfg: &PinConfig) -> Self {
+        Self {
+            pin: cfg.pin,
+            name: cfg
+                .name
+                .clone()
+                .unwrap_or_else(|| format!("pin {}", cfg.pin)),
+            kind: cfg.mode,
+            level: None,
+            on: false,
+            hz: DEFAULT_HZ,
+            duty: 0.5,
+            angle: 0.,
+        }
+    }
+
+    /// Set the pin mode and bring the pin to its state, after (re)connecting
+    pub async fn setup(&mut self, robot: &Robot) -> Result<()> {
+        let mode = match self.kind {
+            PinKind::Input => Mode::Input,
+            _ => Mode::Output,
+        };
+        robot.pin_mode(self.pin, mode).await?;
+        match self.kind {
+            PinKind::Input => self.level = Some(robot.read_pin(self.pin).await?),
+            _ => self.apply(robot).await?,
+        }
+        Ok(())
+    }
+
+    /// Send the state of an output pin to the robot
+    pub async fn apply(&self, robot: &Robot) -> Result<()> {
+        match self.kind {
+            PinKind::Input => Ok(()),
+            PinKind::Output => robot.write_pin(self.pin, self.on).await,
+            PinKind::Pwm => {
+                let duty = if self.on { self.duty } else { 0. };
+                robot.pwm(self.pin, self.hz, duty).await
+            }
+            PinKind::Servo => robot.servo(self.pin, self.angle).await,
+        }
+    }
+
+    /// The command [`apply`](Self::apply) makes, for recordings
+    pub fn describe(&self) -> Option<String> {
+        Some(match self.kind {
+            PinKind::Input => return None,
+            PinKind::Output => format!("write_pin {} {}", self.pin, self.on as u8),
+            PinKind::Pwm => {
+                let duty = if self.on { self.duty } else { 0. };
+                format!("pwm {} {} {duty:.2}", self.pin, self.hz)
+            }
+            PinKind::Servo => format!("servo {} {}", self.pin, self.angle),
+        })
+    }
+
+    /// Move the duty cycle or the servo angle by `steps`, returns whether anything changed
+    pub fn adjust(&mut self, steps: f64) -> bool {
+        match self.kind {
+            PinKind::Pwm => self.duty = (self.duty + steps * DUTY_STEP).clamp(0., 1.),
+            PinKind::Servo => self.angle = (self.angle + steps * ANGLE_STEP).clamp(-90., 90.),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Double or halve the PWM frequency
+    pub fn scale_hz(&mut self, up: bool) -> bool {
+        if self.kind != PinKind::Pwm {
+            return false;
+        }
+        self.hz = if up { self.hz * 2. } else { self.hz / 2. }.clamp(1., MAX_HZ);
+        true
+    }
+
+    /// Turn an output or PWM pin on or off
+    pub fn toggle(&mut self) -> bool {
+        if !matches!(self.kind, PinKind::Output | PinKind::Pwm) {
+            return false;
+        }
+        self.on = !self.on;
+        true
+    }
+}
@@ -0,0 +1,109 @@
+use crate::config;
+use std::{fs::OpenOptions, io::Write, path::PathBuf};
+
+/// Lines kept in the history file
+const MAX_LINES: usize = 1000;
+
+/// The lines typed into the command terminal, saved between runs.
+///
+/// Works like a shell's: Up and Down walk through it with [`prev`](Self::prev) and
+/// [`next`](Self::next), Ctrl-R searches it with [`search`](Self::search).
+#[derive(Debug, Default)]
+pub struct History {
+    lines: Vec<String>,
+    path: Option<PathBuf>,
+    /// the line being recalled, `None` while editing a new one
+    pos: Option<usize>,
+    /// what was typed before recalling started
+    draft: String,
+}
+
+impl History {
+    /// Load the history file, starting empty if there isn't one
+    pub fn load() -> Self {
+        let path = config::data_file("history");
+        let mut lines: Vec<String> = path
+            .as_ref()
+            .and_then(|p| std::fs::read_to_string(p).ok())
+            .map(|s| s.lines().map(str::to_owned).collect())
+            .unwrap_or_default();
+
+        if lines.len() > MAX_LINES {
+            lines.drain(..lines.len() - MAX_LINES);
+            if let Some(path) = &path {
+                if let Err(e) = std::fs::write(path, lines.join("\n") + "\n") {
+                    log::warn!("Failed to trim history file: {e}");
+                }
+            }
+        }
+
+        Self {
+            lines,
+            path,
+            ..Default::default()
+        }
+    }
+
+    /// Add a submitted line, and append it to the history file
+    pub fn push(&mut self, line: &str) {
+        self.pos = None;
+        if self.lines.last().is_some_and(|l| l == line) {
+            return;
+        }
+        self.lines.push(line.to_owned());
+
+        let Some(path) = &self.path else {
+            return;
+        };
+        let res = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut f| writeln!(f, "{line}"));
+        if let Err(e) = res {
+            log::warn!("Failed to write history file: {e}");
+        }
+    }
+
+    /// An older line, `current` is the input, which is restored when going past the newest
+    pub fn prev(&mut self, current: &str) -> Option<&str> {
+        let pos = match self.pos {
+            None if self.lines.is_empty() => return None,
+            None => {
+                self.draft = current.to_owned();
+                self.lines.len() - 1
+            }
+            Some(0) => return None,
+            Some(p) => p - 1,
+        };
+        self.pos = Some(pos);
+        Some(&self.lines[pos])
+    }
+
+    /// A newer line, or the draft after the newest one
+    pub fn next(&mut self) -> Option<&str> {
+        let pos = self.pos?;
+        if pos + 1 < self.lines.len() {
+            self.pos = Some(pos + 1);
+            Some(&self.lines[pos + 1])
+        } else {
+            self.pos = None;
+            Some(&self.draft)
+        }
+    }
+
+    /// Stop recalling, the next [`prev`](Self::prev) starts from the newest line again
+    pub fn reset(&mut self) {
+        self.pos = None;
+    }
+
+    /// Index of the newest line containing `query`, older than `before` if given
+    pub fn search(&self, query: &str, before: Option<usize>) -> Option<usize> {
+        let end = before.unwrap_or(self.lines.len()).min(self.lines.len());
+        self.lines[..end].iter().rposition(|l| l.contains(query))
+    }
+
+    pub fn get(&self, i: usize) -> Option<&str> {
+        self.lines.get(i).map(String::as_str)
+    }
+}
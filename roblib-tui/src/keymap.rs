@@ -0,0 +1,349 @@
+use anyhow::{anyhow, Result};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// Something a key can be bound to, outside of the Cmd Terminal and GPIO tabs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Forward,
+    Left,
+    Backward,
+    Right,
+    SpeedUp,
+    SpeedDown,
+    Stop,
+    Quit,
+    NextTab,
+    PrevTab,
+    Help,
+    Guard,
+    Led,
+    LedColor,
+    Buzzer,
+    ToneDown,
+    ToneUp,
+    ServoLeft,
+    ServoRight,
+    Record,
+    ReplayPause,
+    ReplayStep,
+    ReplayBack,
+    ReplayForward,
+}
+
+impl Action {
+    /// In the order they're shown in the help
+    pub const ALL: [Action; 24] = [
+        Action::Forward,
+        Action::Left,
+        Action::Backward,
+        Action::Right,
+        Action::SpeedUp,
+        Action::SpeedDown,
+        Action::Stop,
+        Action::Guard,
+        Action::Led,
+        Action::LedColor,
+        Action::Buzzer,
+        Action::ToneDown,
+        Action::ToneUp,
+        Action::ServoLeft,
+        Action::ServoRight,
+        Action::Record,
+        Action::ReplayPause,
+        Action::ReplayStep,
+        Action::ReplayBack,
+        Action::ReplayForward,
+        Action::NextTab,
+        Action::PrevTab,
+        Action::Help,
+        Action::Quit,
+    ];
+
+    pub fn describe(self) -> &'static str {
+        match self {
+            Action::Forward => "Drive forward",
+            Action::Left => "Turn left",
+            Action::Backward => "Drive backward",
+            Action::Right => "Turn right",
+            Action::SpeedUp => "Increase drive speed",
+            Action::SpeedDown => "Decrease drive speed",
+            Action::Stop => "Stop driving",
+            Action::Quit => "Quit",
+            Action::NextTab => "Next tab",
+            Action::PrevTab => "Previous tab",
+            Action::Help => "Toggle this help",
+            Action::Guard => "Toggle the collision guard",
+            Action::Led => "Toggle the LED",
+            Action::LedColor => "Change the LED's color",
+            Action::Buzzer => "Toggle the buzzer",
+            Action::ToneDown => "Lower the buzzer's tone",
+            Action::ToneUp => "Raise the buzzer's tone",
+            Action::ServoLeft => "Turn the camera servo left",
+            Action::ServoRight => "Turn the camera servo right",
+            Action::Record => "Start or stop recording the session",
+            Action::ReplayPause => "Pause or resume the replay",
+            Action::ReplayStep => "Play the next replayed event",
+            Action::ReplayBack => "Seek the replay back",
+            Action::ReplayForward => "Seek the replay forward",
+        }
+    }
+
+    /// Whether this works the LED, buzzer or camera servo
+    pub fn is_periph(self) -> bool {
+        matches!(
+            self,
+            Action::Led
+                | Action::LedColor
+                | Action::Buzzer
+                | Action::ToneDown
+                | Action::ToneUp
+                | Action::ServoLeft
+                | Action::ServoRight
+        )
+    }
+
+    /// Whether this controls a `--replay`
+    pub fn is_replay(self) -> bool {
+        matches!(
+            self,
+            Action::ReplayPause | Action::ReplayStep | Action::ReplayBack | Action::ReplayForward
+        )
+    }
+
+    /// The drive key this is, in WASD order
+    pub fn drive_index(self) -> Option<usize> {
+        match self {
+            Action::Forward => Some(0),
+            Action::Left => Some(1),
+            Action::Backward => Some(2),
+            Action::Right => Some(3),
+            _ => None,
+        }
+    }
+}
+
+/// Bindings the `[keys]` of the config start from
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Layout {
+    #[default]
+    Wasd,
+    /// drive with the arrow keys, speed is on PageUp and PageDown
+    Arrows,
+    /// drive with `hjkl`, the LED moves to `o`
+    Vim,
+}
+
+/// The `[keys]` table of the config file.
+///
+/// ```toml
+/// [keys]
+/// layout = "vim"
+/// quit = ["Q", "Esc"]
+/// speed_up = ["Up", "PageUp"]
+/// record = "Ctrl-r"
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct KeyConfig {
+    pub layout: Layout,
+    /// replaces the layout's keys for these actions
+    #[serde(flatten)]
+    pub bind: HashMap<Action, OneOrMany>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum OneOrMany {
+    One(String),
+    Many(Vec<String>),
+}
+
+/// A key with the modifiers held down with it. Shift isn't one, it's already in the character.
+type Key = (KeyCode, KeyModifiers);
+
+/// Which action each key does
+#[derive(Debug, Clone)]
+pub struct Keymap(Vec<(Key, Action)>);
+
+impl Keymap {
+    pub fn new(cfg: &KeyConfig) -> Result<Self> {
+        let mut binds = Self::layout(cfg.layout);
+        for (action, keys) in &cfg.bind {
+            binds.retain(|(_, a)| a != action);
+            let keys = match keys {
+                OneOrMany::One(k) => std::slice::from_ref(k),
+                OneOrMany::Many(k) => k.as_slice(),
+            };
+            for key in keys {
+                let key = parse_key(key)?;
+                binds.retain(|(k, _)| *k != key);
+                binds.push((key, *action));
+            }
+        }
+        Ok(Self(binds))
+    }
+
+    fn layout(layout: Layout) -> Vec<(Key, Action)> {
+        use Action::*;
+        use KeyCode::{BackTab, Char, Down, PageDown, PageUp, Tab, Up};
+
+        let mut binds = vec![
+            (Char(' '), Stop),
+            (Char('Q'), Quit),
+            (Tab, NextTab),
+            (BackTab, PrevTab),
+            (Char('?'), Help),
+            (Char('g'), Guard),
+            (Char('G'), Guard),
+            (Char('c'), LedColor),
+            (Char('C'), LedColor),
+            (Char('b'), Buzzer),
+            (Char('B'), Buzzer),
+            (Char(','), ToneDown),
+            (Char('.'), ToneUp),
+            (Char('r'), Record),
+            (Char('R'), Record),
+            (KeyCode::Enter, ReplayPause),
+            (Char('n'), ReplayStep),
+            (Char('<'), ReplayBack),
+            (Char('>'), ReplayForward),
+        ];
+        let drive = match layout {
+            Layout::Wasd => "wasd",
+            Layout::Arrows => "",
+            Layout::Vim => "khjl",
+        };
+        for (c, action) in drive.chars().zip([Forward, Left, Backward, Right]) {
+            binds.push((Char(c), action));
+            binds.push((Char(c.to_ascii_uppercase()), action));
+        }
+
+        binds.extend(match layout {
+            Layout::Wasd => vec![
+                (Up, SpeedUp),
+                (Down, SpeedDown),
+                (Char('l'), Led),
+                (Char('L'), Led),
+                (KeyCode::Left, ServoLeft),
+                (KeyCode::Right, ServoRight),
+            ],
+            Layout::Arrows => vec![
+                (Up, Forward),
+                (KeyCode::Left, Left),
+                (Down, Backward),
+                (KeyCode::Right, Right),
+                (PageUp, SpeedUp),
+                (PageDown, SpeedDown),
+                (Char('l'), Led),
+                (Char('L'), Led),
+                (Char('['), ServoLeft),
+                (Char(']'), ServoRight),
+            ],
+            Layout::Vim => vec![
+                (Up, SpeedUp),
+                (Down, SpeedDown),
+                (Char('o'), Led),
+                (Char('O'), Led),
+                (KeyCode::Left, ServoLeft),
+                (KeyCode::Right, ServoRight),
+            ],
+        });
+        binds
+            .into_iter()
+            .map(|(code, action)| ((code, KeyModifiers::NONE), action))
+            .collect()
+    }
+
+    pub fn get(&self, key: &KeyEvent) -> Option<Action> {
+        let key = (key.code, key.modifiers - KeyModifiers::SHIFT);
+        self.0.iter().find(|(k, _)| *k == key).map(|(_, a)| *a)
+    }
+
+    /// The keys bound to `action`, for the help
+    pub fn keys(&self, action: Action) -> Vec<String> {
+        self.0
+            .iter()
+            .filter(|(_, a)| *a == action)
+            .map(|(k, _)| key_name(*k))
+            .collect()
+    }
+
+    /// The keys bound to `action` for a panel title, a letter bound in both cases only once
+    pub fn hint(&self, action: Action) -> String {
+        let mut names: Vec<String> = vec![];
+        for key in self.keys(action) {
+            if !names.iter().any(|n| n.eq_ignore_ascii_case(&key)) {
+                names.push(key);
+            }
+        }
+        names.join("/")
+    }
+}
+
+/// Parse a key from the config: a single character or a name like `Up` or `F1`, after any of
+/// `Ctrl-` and `Alt-`
+fn parse_key(s: &str) -> Result<Key> {
+    let mut modifiers = KeyModifiers::NONE;
+    let mut rest = s;
+    // a lone `-` is a key
+    while let Some((m, key)) = rest.split_once('-').filter(|(_, key)| !key.is_empty()) {
+        modifiers |= match m.to_lowercase().as_str() {
+            "ctrl" => KeyModifiers::CONTROL,
+            "alt" => KeyModifiers::ALT,
+            _ => break,
+        };
+        rest = key;
+    }
+    let code = parse_code(rest).ok_or_else(|| anyhow!("Unknown key in config: {s}"))?;
+    Ok((code, modifiers))
+}
+
+fn parse_code(s: &str) -> Option<KeyCode> {
+    let mut chars = s.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Some(KeyCode::Char(c));
+    }
+
+    Some(match s.to_lowercase().as_str() {
+        "space" => KeyCode::Char(' '),
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "pageup" => KeyCode::PageUp,
+        "pagedown" => KeyCode::PageDown,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "tab" => KeyCode::Tab,
+        "backtab" => KeyCode::BackTab,
+        "enter" => KeyCode::Enter,
+        "esc" => KeyCode::Esc,
+        "backspace" => KeyCode::Backspace,
+        "delete" => KeyCode::Delete,
+        "insert" => KeyCode::Insert,
+        f => match f.strip_prefix('f').and_then(|n| n.parse().ok()) {
+            Some(n @ 1..=12) => KeyCode::F(n),
+            _ => return None,
+        },
+    })
+}
+
+fn key_name((code, modifiers): Key) -> String {
+    let mut name = String::new();
+    if modifiers.contains(KeyModifiers::CONTROL) {
+        name.push_str("Ctrl-");
+    }
+    if modifiers.contains(KeyModifiers::ALT) {
+        name.push_str("Alt-");
+    }
+    name + &match code {
+        KeyCode::Char(' ') => "Space".to_owned(),
+        KeyCode::Char(c) => c.to_string(),
+        KeyCode::F(n) => format!("F{n}"),
+        code => format!("{code:?}"),
+    }
+}
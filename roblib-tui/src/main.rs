@@ -1,118 +1,278 @@
+mod config;
+mod conn;
+mod dispatch;
+mod gamepad;
+mod gpio;
+mod history;
+mod keymap;
+mod odometry;
+mod periph;
+mod record;
 mod render;
+mod replay;
+mod script;
+mod shell;
+mod sim;
+mod subs;
+mod watchdog;
 
-use anyhow::Result;
-use clap::Parser;
-use roblib_client::{
-    roblib::cmd::Concrete,
-    transports::{tcp::TcpAsync, TransportAsync},
-    RobotAsync,
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
+use drive::{Calibration, Watchdog};
+use roblib_client::{roblib::cmd::Concrete, transports::tcp::TcpAsync, RobotAsync};
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
 };
+use tokio_util::sync::CancellationToken;
 
 #[derive(Debug, Parser)]
 #[command(author, version)]
 struct Args {
+    #[command(subcommand)]
+    cmd: Option<Cmd>,
+
     #[arg(short, long)]
     exec: Option<String>,
 
+    /// Line-oriented shell instead of the TUI
     #[arg(short, long)]
     shell: bool,
+
+    /// Robot profile from the config file
+    #[arg(short, long)]
+    robot: Option<String>,
+
+    /// Robot address, overrides the profile
+    #[arg(long, env = "ROBLIB_HOST")]
+    host: Option<String>,
+
+    /// Robot port, overrides the profile
+    #[arg(short, long, env = "ROBLIB_PORT")]
+    port: Option<u16>,
+
+    /// Only drive while the WASD keys are held down
+    #[arg(long)]
+    hold: bool,
+
+    /// Milliseconds a key counts as held after its last repeat, in case the terminal doesn't
+    /// report its release
+    #[arg(long, default_value_t = 500)]
+    hold_timeout: u64,
+
+    /// Stop the robot when there's no input for this many milliseconds
+    #[arg(long, env = "ROBLIB_WATCHDOG")]
+    watchdog: Option<u64>,
+
+    /// Milliseconds between ultra sensor readings
+    #[arg(long, default_value_t = 100)]
+    ultra_interval: u64,
+
+    /// Obstacle distance in cm marked on the ultra sensor chart, the collision guard stops
+    /// driving forward closer than this
+    #[arg(long, default_value_t = 30.)]
+    ultra_threshold: f64,
+
+    /// Start with the collision guard on, toggle it with `G`
+    #[arg(long)]
+    guard: bool,
+
+    /// Record the sensor readings and the commands sent to this file, as CSV if it ends in
+    /// `.csv`, JSON Lines otherwise. `R` starts and stops recording
+    #[arg(long)]
+    record: Option<PathBuf>,
+
+    /// Play back a recording made with `--record` instead of connecting to a robot
+    #[arg(long)]
+    replay: Option<PathBuf>,
+
+    /// Playback rate of `--replay`, 2 plays twice as fast as recorded
+    #[arg(long, default_value_t = 1.)]
+    replay_speed: f64,
+
+    /// roblib-sim's view feed, to show where the simulated robot really is on the Map tab
+    #[arg(long, env = "ROBLIB_SIM_VIEW")]
+    sim: Option<String>,
+
+    /// Joystick device to drive with, `/dev/input/js*` or `/dev/input/event*`, or a recording of
+    /// one to replay, read as evdev events if it ends in `.evdev`. Overrides the config
+    #[arg(long, env = "ROBLIB_GAMEPAD")]
+    gamepad: Option<PathBuf>,
 }
 
-const IP: &str = "10.0.0.236:1110";
+#[derive(Debug, Subcommand)]
+enum Cmd {
+    /// Run a script of text format commands, with `sleep <ms>`, `repeat <n> { }`, `let`, `pose`
+    /// and `#`
+    Run {
+        script: PathBuf,
+
+        /// Only parse and check the script, without connecting to the robot
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Measure the range of the gamepad's sticks and triggers
+    CalibrateGamepad,
+    /// Drive a known distance and measure how far the robot really went, so the Map tab and
+    /// scripts know where it is
+    CalibrateOdometry {
+        /// Meters to drive straight ahead
+        #[arg(long, default_value_t = 1.)]
+        distance: f64,
+
+        /// Wheel speed to drive at, `0..=1`
+        #[arg(long, default_value_t = 0.5)]
+        speed: f64,
+    },
+}
 
 #[tokio::main(flavor = "current_thread")]
 async fn main() -> Result<()> {
     let args = Args::parse();
 
-    let robot = RobotAsync::new(TcpAsync::connect(IP).await?);
+    let script = match &args.cmd {
+        Some(Cmd::Run { script, dry_run }) => {
+            let src = std::fs::read_to_string(script)
+                .with_context(|| format!("Failed to read {}", script.display()))?;
+            let script = script::Script::parse(&src)?;
+            if *dry_run {
+                // nothing moves, so the pose is only there to be substituted
+                script.run(None, Calibration::default()).await?;
+                println!("Script is valid");
+                return Ok(());
+            }
+            Some(script)
+        }
+        _ => None,
+    };
+
+    let config = config::Config::load()?;
+
+    let gamepad = args.gamepad.as_ref().or(config.gamepad.device.as_ref());
+    if let Some(Cmd::CalibrateGamepad) = args.cmd {
+        let path = gamepad.context("No gamepad given, use --gamepad or the config file")?;
+        return gamepad::calibrate(path);
+    }
+
+    let addr = config.addr(args.robot.as_deref(), args.host.as_deref(), args.port)?;
+    if let Some(script) = script {
+        let calibration = config.odometry.calibration()?;
+        let robot = RobotAsync::new(TcpAsync::connect(&addr).await?);
+        script.run(Some(&robot), calibration).await?;
+        // needed to ensure send before exit
+        tokio::task::yield_now().await;
+        return Ok(());
+    }
+
+    if let Some(Cmd::CalibrateOdometry { distance, speed }) = args.cmd {
+        let robot = RobotAsync::new(TcpAsync::connect(&addr).await?);
+        odometry::calibrate(&robot, &config.odometry, distance, speed).await?;
+        // needed to ensure send before exit
+        tokio::task::yield_now().await;
+        return Ok(());
+    }
+
+    if args.shell {
+        let robot = RobotAsync::new(TcpAsync::connect(&addr).await?);
+        return shell::run(robot, config.odometry.calibration()?).await;
+    }
 
     if let Some(txt) = args.exec {
+        let robot = RobotAsync::new(TcpAsync::connect(&addr).await?);
         let cmd: Concrete = roblib_client::roblib::text_format::de::from_str(&txt)?;
-        dbg!(&cmd);
-        let ret = execute(cmd, &robot.transport).await?;
-        if let Some(s) = ret {
-            println!("{s}");
+        if let Concrete::Subscribe(c) = cmd {
+            let started = Instant::now();
+            let mut subs = subs::Subs::new(Arc::new(move |_, v| {
+                println!("[{:>9.3}s] {v:?}", started.elapsed().as_secs_f64());
+                true
+            }));
+            subs.subscribe(&robot, c.0).await?;
+            tokio::signal::ctrl_c().await?;
+            subs.unsubscribe_all(Some(&robot)).await?;
+            return Ok(());
+        }
+        let ret = dispatch::execute(cmd, &robot.transport).await?;
+        if let Some(v) = ret {
+            println!("{}", dispatch::show(&v));
         }
         // needed to ensure send before exit
         tokio::task::yield_now().await;
         return Ok(());
     }
 
-    let (h1, h2) = render::TUI::new(robot).await?.spawn();
-    let (h1, h2) = tokio::join!(h1, h2);
-    h1??;
-    h2??;
+    let replay = args
+        .replay
+        .as_deref()
+        .map(|path| replay::Replay::load(path, args.replay_speed))
+        .transpose()?;
+    let replaying = replay.is_some();
+    let conn = match replay {
+        Some(_) => conn::Conn::offline(),
+        None => conn::Conn::connect(addr).await?,
+    };
+    let watchdog = Arc::new(Mutex::new(Watchdog::new(
+        args.watchdog.map(Duration::from_millis),
+    )));
 
-    Ok(())
-}
+    // shared by all tasks, whichever ends first takes the others down with it
+    let cancel = CancellationToken::new();
 
-// L workaround
-pub(crate) async fn execute(cmd: Concrete, robot: &impl TransportAsync) -> Result<Option<String>> {
-    Ok(match cmd {
-        Concrete::MoveRobot(c) => {
-            robot.cmd(c).await?;
-            None
-        }
-        Concrete::MoveRobotByAngle(c) => {
-            robot.cmd(c).await?;
-            None
-        }
-        Concrete::StopRobot(c) => {
-            robot.cmd(c).await?;
-            None
-        }
-        Concrete::Led(c) => {
-            robot.cmd(c).await?;
-            None
-        }
-        Concrete::RolandServo(c) => {
-            robot.cmd(c).await?;
-            None
-        }
-        Concrete::Buzzer(c) => {
-            robot.cmd(c).await?;
-            None
-        }
-        Concrete::TrackSensor(c) => Some(format!("{:?}", robot.cmd(c).await?)),
-        Concrete::UltraSensor(c) => Some(format!("{}", robot.cmd(c).await?)),
+    let hold = args.hold.then(|| Duration::from_millis(args.hold_timeout));
+    let ultra = render::UltraOpts {
+        interval: Duration::from_millis(args.ultra_interval),
+        threshold: args.ultra_threshold,
+        guard: args.guard,
+    };
+    // open it before the terminal is taken over, so errors can be seen
+    let gamepad = gamepad
+        .map(|path| gamepad::Gamepad::open(path, &config.gamepad))
+        .transpose()?;
+    let tui = render::TUI::new(
+        conn.clone(),
+        hold,
+        watchdog.clone(),
+        ultra,
+        args.record,
+        replay,
+        &config,
+    )
+    .await?;
+    if let Some(gamepad) = gamepad {
+        gamepad.spawn(tui.tx(), cancel.clone());
+    }
+    if let Some(addr) = args.sim {
+        sim::spawn(addr, tui.tx(), cancel.clone());
+    }
+    // nothing to reconnect to while replaying
+    let h4 = if replaying {
+        None
+    } else {
+        Some(tokio::spawn(conn::run(
+            conn.clone(),
+            tui.tx(),
+            cancel.clone(),
+        )))
+    };
+    let tx = tui.tx();
+    let (h1, h2) = tui.spawn(cancel.clone());
+    let h3 = tokio::spawn(watchdog::run(conn.clone(), watchdog, tx, cancel));
 
-        Concrete::PinMode(c) => {
-            robot.cmd(c).await?;
-            None
-        }
-        Concrete::ReadPin(c) => Some(format!("{}", robot.cmd(c).await?)),
-        Concrete::WritePin(c) => {
-            robot.cmd(c).await?;
-            None
-        }
-        Concrete::Pwm(c) => {
-            robot.cmd(c).await?;
-            None
-        }
-        Concrete::Servo(c) => {
-            robot.cmd(c).await?;
-            None
-        }
+    let (r1, r2, r3) = tokio::join!(h1, h2, h3);
+    let r4 = match h4 {
+        Some(h4) => h4.await,
+        None => Ok(Ok(())),
+    };
 
-        Concrete::Subscribe(_) => Some(format!("Subscribe no supported")),
-        Concrete::Unsubscribe(_) => Some(format!("Unsubscribe no supported")),
+    // the UI task stops the robot on its way out, unless it panicked
+    if let (Err(_), Some(robot)) = (&r1, conn.robot()) {
+        robot.stop().await?;
+    }
+    r1??;
+    r2??;
+    r3??;
+    r4??;
 
-        Concrete::Nop(c) => {
-            robot.cmd(c).await?;
-            None
-        }
-        Concrete::GetUptime(c) => Some(format!("{:?}", robot.cmd(c).await?)),
-
-        // Concrete::GetPosition(c) => {
-        //     if let Some(p) = robot.cmd(c).await? {
-        //         println!("{}", p)
-        //     } else {
-        //         println!("<")
-        //     }
-        // }
-        Concrete::Abort(c) => {
-            robot.cmd(c).await?;
-            None
-        }
-    })
+    println!("Bye!");
+    Ok(())
 }
@@ -0,0 +1,130 @@
+use crate::{config, render::Robot};
+use anyhow::{bail, Context, Result};
+use drive::Calibration;
+use roblib_client::roblib::roland::RolandAsync;
+use serde::{Deserialize, Serialize};
+use std::{io::ErrorKind, time::Duration};
+
+/// where the speed measured by [`calibrate`] is saved
+const CALIBRATION_FILE: &str = "odometry.toml";
+
+/// The `[odometry]` table of the config file, for guessing where the robot went from the
+/// speeds it was driven at.
+///
+/// ```toml
+/// [odometry]
+/// # meters between the wheels
+/// wheel_base = 0.15
+/// # m/s of a wheel driven at full speed, `calibrate-odometry` measures it
+/// max_speed = 0.5
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct OdometryConfig {
+    pub wheel_base: f64,
+    pub max_speed: f64,
+}
+
+impl Default for OdometryConfig {
+    fn default() -> Self {
+        let Calibration {
+            wheel_base,
+            max_speed,
+        } = Calibration::default();
+        Self {
+            wheel_base,
+            max_speed,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Saved {
+    max_speed: f64,
+}
+
+impl OdometryConfig {
+    /// The config's calibration, with the speed measured by [`calibrate`] if there is one
+    pub fn calibration(&self) -> Result<Calibration> {
+        let mut cal = Calibration {
+            wheel_base: self.wheel_base,
+            max_speed: self.max_speed,
+        };
+        let Some(path) = config::data_file(CALIBRATION_FILE) else {
+            return Ok(cal);
+        };
+        match std::fs::read_to_string(&path) {
+            Ok(txt) => {
+                let saved: Saved = toml::from_str(&txt)
+                    .with_context(|| format!("Failed to parse {}", path.display()))?;
+                cal.max_speed = saved.max_speed;
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => (),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        }
+        Ok(cal)
+    }
+}
+
+/// Drive straight for what should be `distance` meters at `speed`, then ask how far the robot
+/// really went and save the corrected speed
+pub async fn calibrate(
+    robot: &Robot,
+    config: &OdometryConfig,
+    distance: f64,
+    speed: f64,
+) -> Result<()> {
+    if !(distance > 0. && speed > 0. && speed <= 1.) {
+        bail!("The distance has to be positive and the speed in 0..=1");
+    }
+    let mut cal = config.calibration()?;
+    let secs = cal.time_for(distance, speed);
+
+    println!("The robot will drive {distance} m straight ahead, taking {secs:.1}s");
+    ask("Mark where it stands, make room and press Enter").await?;
+    robot.drive(speed, speed).await?;
+    // like the controller's `StopOnDrop`, but a drop can't wait for the stop to be sent, so
+    // Ctrl-C ends the drive here instead of killing the process with the robot still going
+    let finished = tokio::select! {
+        _ = tokio::time::sleep(Duration::from_secs_f64(secs)) => true,
+        _ = tokio::signal::ctrl_c() => false,
+    };
+    robot.stop().await?;
+    if !finished {
+        bail!("Interrupted, nothing was saved");
+    }
+
+    let measured = loop {
+        match ask("How far did it go, in meters?")
+            .await?
+            .trim()
+            .parse::<f64>()
+        {
+            Ok(m) if m > 0. => break m,
+            _ => println!("That's not a distance"),
+        }
+    };
+    let was = cal.max_speed;
+    cal.correct_speed(speed, secs, measured);
+    println!("Full speed is {:.3} m/s, was {was:.3} m/s", cal.max_speed);
+
+    let path = config::data_file(CALIBRATION_FILE)
+        .context("No data directory to save the calibration in")?;
+    let saved = Saved {
+        max_speed: cal.max_speed,
+    };
+    std::fs::write(&path, toml::to_string(&saved)?)?;
+    println!("Saved to {}", path.display());
+    Ok(())
+}
+
+/// Print `prompt` and read a line, without holding up the connection's task
+async fn ask(prompt: &str) -> Result<String> {
+    println!("{prompt}");
+    let line = tokio::task::spawn_blocking(|| {
+        let mut line = String::new();
+        std::io::stdin().read_line(&mut line).map(|_| line)
+    })
+    .await??;
+    Ok(line)
+}
@@ -0,0 +1,108 @@
+use crate::render::Robot;
+use anyhow::Result;
+use ratatui::style::Color;
+use roblib_client::roblib::roland::RolandAsync;
+
+/// Colors the RGB LED can show, it can only switch each channel on or off
+pub const COLORS: [(&str, [bool; 3], Color); 7] = [
+    ("red", [true, false, false], Color::Red),
+    ("green", [false, true, false], Color::Green),
+    ("blue", [false, false, true], Color::Blue),
+    ("yellow", [true, true, false], Color::Yellow),
+    ("cyan", [false, true, true], Color::Cyan),
+    ("magenta", [true, false, true], Color::Magenta),
+    ("white", [true, true, true], Color::White),
+];
+
+/// change of the buzzer's tone per step
+const TONE_STEP: f64 = 0.1;
+/// change of the servo angle per step, in degrees
+const SERVO_STEP: f64 = 5.;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Device {
+    Led,
+    Buzzer,
+    Servo,
+}
+
+/// The Roland's LED, buzzer and camera servo, as last sent to the robot
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Peripherals {
+    pub led: bool,
+    /// index into [`COLORS`]
+    pub color: usize,
+    pub buzzer: bool,
+    /// the buzzer only takes a pulse width, which changes its tone, `0..1`
+    pub tone: f64,
+    /// camera servo angle in degrees, `-90..=90`
+    pub servo: f64,
+}
+
+impl Default for Peripherals {
+    fn default() -> Self {
+        Self {
+            led: false,
+            color: 0,
+            buzzer: false,
+            tone: 0.5,
+            servo: 0.,
+        }
+    }
+}
+
+impl Peripherals {
+    pub fn color_name(&self) -> &'static str {
+        COLORS[self.color].0
+    }
+
+    /// The color to draw the LED with
+    pub fn color(&self) -> Color {
+        COLORS[self.color].2
+    }
+
+    pub fn next_color(&mut self) {
+        self.color = (self.color + 1) % COLORS.len();
+    }
+
+    pub fn adjust_tone(&mut self, steps: f64) {
+        // a pulse width of 1 is silent
+        self.tone = (self.tone + steps * TONE_STEP).clamp(TONE_STEP, 1. - TONE_STEP);
+    }
+
+    pub fn adjust_servo(&mut self, steps: f64) {
+        self.servo = (self.servo + steps * SERVO_STEP).clamp(-90., 90.);
+    }
+
+    /// The command [`send`](Self::send) makes, for recordings
+    pub fn describe(&self, device: Device) -> String {
+        match device {
+            Device::Led => {
+                let [r, g, b] = if self.led {
+                    COLORS[self.color].1
+                } else {
+                    [false; 3]
+                };
+                format!("led {} {} {}", r as u8, g as u8, b as u8)
+            }
+            Device::Buzzer => format!("buzzer {:.2}", if self.buzzer { self.tone } else { 1. }),
+            Device::Servo => format!("roland_servo {}", self.servo),
+        }
+    }
+
+    /// Send the state of one device to the robot
+    pub async fn send(&self, robot: &Robot, device: Device) -> Result<()> {
+        match device {
+            Device::Led => {
+                let [r, g, b] = if self.led {
+                    COLORS[self.color].1
+                } else {
+                    [false; 3]
+                };
+                robot.led(r, g, b).await
+            }
+            Device::Buzzer => robot.buzzer(if self.buzzer { self.tone } else { 1. }).await,
+            Device::Servo => robot.roland_servo(self.servo).await,
+        }
+    }
+}
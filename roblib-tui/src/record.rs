@@ -0,0 +1,274 @@
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{File, OpenOptions},
+    io::{LineWriter, Write},
+    path::{Path, PathBuf},
+};
+
+/// One line of a recording
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    /// seconds since the TUI started, so entries of a session appended to one file stay ordered
+    pub at: f64,
+    #[serde(flatten)]
+    pub record: Record,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Record {
+    Track {
+        sensors: [bool; 4],
+    },
+    /// distance in meters
+    Ultra {
+        distance: f64,
+    },
+    Gpio {
+        pin: u8,
+        level: bool,
+    },
+    /// a value from one of the user's subscriptions
+    Event {
+        id: u32,
+        value: String,
+    },
+    /// a command sent to the robot, in the same words the TUI shows it
+    Cmd {
+        cmd: String,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    JsonLines,
+    /// `at,type,id,value` columns, see [`Entry::to_csv`]
+    Csv,
+}
+
+impl Format {
+    /// CSV for `.csv` files, JSON Lines for anything else
+    pub fn of(path: &Path) -> Self {
+        match path.extension() {
+            Some(ext) if ext.eq_ignore_ascii_case("csv") => Self::Csv,
+            _ => Self::JsonLines,
+        }
+    }
+}
+
+impl Entry {
+    /// A CSV row, `id` is the pin or subscription id, a track reading is written as 4 bits
+    pub fn to_csv(&self) -> String {
+        let (kind, id, value) = match &self.record {
+            Record::Track { sensors } => (
+                "track",
+                String::new(),
+                sensors.iter().map(|&s| if s { '1' } else { '0' }).collect(),
+            ),
+            Record::Ultra { distance } => ("ultra", String::new(), distance.to_string()),
+            Record::Gpio { pin, level } => ("gpio", pin.to_string(), (*level as u8).to_string()),
+            Record::Event { id, value } => ("event", id.to_string(), csv_quote(value)),
+            Record::Cmd { cmd } => ("cmd", String::new(), csv_quote(cmd)),
+        };
+        format!("{:.3},{kind},{id},{value}", self.at)
+    }
+
+    /// Parse a row written by [`to_csv`](Self::to_csv)
+    pub fn from_csv(line: &str) -> Result<Self> {
+        let mut fields = csv_fields(line).into_iter();
+        let mut next = || fields.next().context("Missing column");
+        let at = next()?.parse()?;
+        let (kind, id, value) = (next()?, next()?, next()?);
+
+        let record = match kind.as_str() {
+            "track" => Record::Track {
+                sensors: value
+                    .chars()
+                    .map(|c| c == '1')
+                    .collect::<Vec<_>>()
+                    .try_into()
+                    .map_err(|_| anyhow!("Expected 4 track sensors, got {value}"))?,
+            },
+            "ultra" => Record::Ultra {
+                distance: value.parse()?,
+            },
+            "gpio" => Record::Gpio {
+                pin: id.parse()?,
+                level: value == "1",
+            },
+            "event" => Record::Event {
+                id: id.parse()?,
+                value,
+            },
+            "cmd" => Record::Cmd { cmd: value },
+            k => bail!("Unknown record type: {k}"),
+        };
+        Ok(Self { at, record })
+    }
+}
+
+/// Read a whole recording, in either format
+pub fn load(path: &Path) -> Result<Vec<Entry>> {
+    let src = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let format = Format::of(path);
+    src.lines()
+        .enumerate()
+        // skip the header
+        .skip((format == Format::Csv) as usize)
+        .filter(|(_, l)| !l.trim().is_empty())
+        .map(|(i, l)| {
+            match format {
+                Format::JsonLines => serde_json::from_str(l).map_err(Into::into),
+                Format::Csv => Entry::from_csv(l),
+            }
+            .with_context(|| format!("{}:{}", path.display(), i + 1))
+        })
+        .collect()
+}
+
+fn csv_quote(s: &str) -> String {
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_owned()
+    }
+}
+
+/// Split a CSV row, undoing [`csv_quote`]
+fn csv_fields(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                fields.last_mut().unwrap().push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(String::new()),
+            c => fields.last_mut().unwrap().push(c),
+        }
+    }
+    fields
+}
+
+/// Writes the events received and the commands sent to a file, a line each
+#[derive(Debug)]
+pub struct Recorder {
+    out: LineWriter<File>,
+    format: Format,
+    path: PathBuf,
+}
+
+impl Recorder {
+    /// Start a recording, `append` continues an earlier one of the same session
+    pub fn create(path: &Path, append: bool) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(append)
+            .truncate(!append)
+            .open(path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        let empty = file.metadata()?.len() == 0;
+
+        let format = Format::of(path);
+        let mut out = LineWriter::new(file);
+        if format == Format::Csv && empty {
+            writeln!(out, "at,type,id,value")?;
+        }
+        Ok(Self {
+            out,
+            format,
+            path: path.to_owned(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn write(&mut self, entry: &Entry) -> Result<()> {
+        match self.format {
+            Format::JsonLines => {
+                serde_json::to_writer(&mut self.out, entry)?;
+                writeln!(self.out)?;
+            }
+            Format::Csv => writeln!(self.out, "{}", entry.to_csv())?,
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries() -> Vec<Entry> {
+        [
+            Record::Track {
+                sensors: [true, false, false, true],
+            },
+            Record::Ultra { distance: 0.42 },
+            Record::Gpio {
+                pin: 17,
+                level: true,
+            },
+            Record::Event {
+                id: 3,
+                value: "GpioPin(5), true".into(),
+            },
+            Record::Cmd {
+                cmd: "drive 0.50 -0.25".into(),
+            },
+            Record::Cmd {
+                cmd: r#"say "hi, there", twice"#.into(),
+            },
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(i, record)| Entry {
+            at: i as f64 * 0.25,
+            record,
+        })
+        .collect()
+    }
+
+    fn round_trip(ext: &str) {
+        let path =
+            std::env::temp_dir().join(format!("roblib-tui-record-{}.{ext}", std::process::id()));
+        let mut recorder = Recorder::create(&path, false).unwrap();
+        for entry in entries() {
+            recorder.write(&entry).unwrap();
+        }
+        drop(recorder);
+        let loaded = load(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap(), entries());
+    }
+
+    #[test]
+    fn json_lines() {
+        round_trip("jsonl");
+    }
+
+    #[test]
+    fn csv() {
+        round_trip("csv");
+    }
+
+    #[test]
+    fn csv_quoting() {
+        let entry = &entries()[5];
+        assert_eq!(entry.to_csv(), r#"1.250,cmd,,"say ""hi, there"", twice""#);
+        assert_eq!(
+            csv_fields(&entry.to_csv()),
+            ["1.250", "cmd", "", r#"say "hi, there", twice"#]
+        );
+        // only what needs it is quoted
+        assert_eq!(csv_quote("drive 1 1"), "drive 1 1");
+    }
+}
@@ -1,32 +1,110 @@
+use crate::{
+    config::{self, Config, PinKind},
+    conn::{Conn, Status},
+    dispatch,
+    gamepad::Pad,
+    gpio::Pin,
+    history::History,
+    keymap::{Action, Keymap},
+    periph::{Device, Peripherals},
+    record::{Entry, Record, Recorder},
+    replay::{self, Replay},
+    sim::{self, SimMap, SimView},
+    subs::Subs,
+};
 use anyhow::Result;
 use crossterm::{
-    event::{EventStream, KeyCode, KeyModifiers},
+    event::{
+        DisableMouseCapture, EnableMouseCapture, EventStream, KeyCode, KeyEvent, KeyEventKind,
+        KeyModifiers, KeyboardEnhancementFlags, MouseButton, MouseEvent, MouseEventKind,
+        PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+    },
     execute,
-    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+    terminal::{
+        disable_raw_mode, enable_raw_mode, supports_keyboard_enhancement, EnterAlternateScreen,
+        LeaveAlternateScreen,
+    },
 };
+use drive::{roland, CollisionGuard, DriveMixer, Odometry, Pose, Watchdog};
 use futures::{FutureExt, StreamExt};
-use ratatui::{prelude::*, widgets::*};
+use ratatui::{
+    prelude::*,
+    widgets::{
+        canvas::{self, Canvas, Circle, Points},
+        *,
+    },
+};
 use roblib_client::{
-    roblib::{event::ConcreteValue, roland::RolandAsync},
+    roblib::{
+        event::{self, ConcreteType, ConcreteValue},
+        roland::RolandAsync,
+    },
     transports::tcp::TcpAsync,
     RobotAsync,
 };
-use std::{fmt::Debug, io::Stdout, time::Duration};
-use tokio::task::JoinHandle;
+use std::{
+    collections::VecDeque,
+    fmt::Debug,
+    io::Stdout,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::{sync::broadcast::Receiver, task::JoinHandle};
+use tokio_util::sync::CancellationToken;
 use tui_input::{backend::crossterm::EventHandler, Input};
 
-type Tx = tokio::sync::broadcast::Sender<Msg>;
-type Robot = RobotAsync<TcpAsync>;
+pub(crate) type Tx = tokio::sync::broadcast::Sender<Msg>;
+pub(crate) type Robot = RobotAsync<TcpAsync>;
 
-static TABS: [&str; 3] = ["Main", "Ultra sensor", "Cmd Terminal"];
+static TABS: [&str; 5] = ["Main", "Ultra sensor", "Cmd Terminal", "GPIO", "Map"];
+/// positions kept for the path drawn behind the robot
+const TRAIL_LEN: usize = 2000;
+/// seconds between the poses worked out when seeking a replay
+const RECKON_STEP: f64 = 0.1;
+/// readings kept for the ultra sensor chart
+const ULTRA_SAMPLES: usize = 200;
+
+/// Settings of the ultra sensor tab
+#[derive(Debug, Clone, Copy)]
+pub struct UltraOpts {
+    pub interval: Duration,
+    /// obstacle distance in cm, drawn as a line on the chart and used by the collision guard
+    pub threshold: f64,
+    /// start with the collision guard on
+    pub guard: bool,
+}
 
 pub struct TUI {
     term: Terminal<CrosstermBackend<Stdout>>,
+    /// keyboard enhancement flags were pushed and need to be popped on exit
+    enhanced: bool,
+    restored: bool,
 
-    robot: Robot,
+    conn: Arc<Conn>,
+    tx: Tx,
+    subs: Subs,
+    started: Instant,
+    mixer: DriveMixer,
+    watchdog: Arc<Mutex<Watchdog>>,
+    ultra_interval: Duration,
+    keys: Keymap,
+    areas: Areas,
+    recorder: Option<Recorder>,
+    /// where the record key saves to, a new file in the data dir each time if `None`
+    record_path: Option<PathBuf>,
 
     s: State,
 }
+/// Where the clickable parts were drawn last, zero sized when they're not on screen
+#[derive(Debug, Default)]
+struct Areas {
+    tabs: Rect,
+    controls: Rect,
+    speed: Rect,
+    history: Rect,
+    chart: Rect,
+}
 #[derive(Debug, Default)]
 struct State {
     index: usize,
@@ -35,187 +113,1037 @@ struct State {
 
     input: Input,
     show_err: Option<(String, bool)>,
-    cmd_hist: Vec<(String, Option<String>)>,
+    cmd_hist: Vec<HistEntry>,
+    history: History,
+    search: Option<Search>,
+    /// the entry of `cmd_hist` selected for re-running
+    hist_sel: Option<usize>,
 
     speed: f64,
     drive: [bool; 4],
     redrive: bool,
+    hold: Option<Hold>,
+    held: [Option<Instant>; 4],
+    /// wheel speeds from the gamepad's sticks, used instead of the keys while it's `Some`
+    analog: Option<(f64, f64)>,
+    /// the drive key held down with the mouse
+    mouse_key: Option<usize>,
+    /// the speed gauge is being dragged
+    mouse_speed: bool,
 
     track: [bool; 4],
-    ultra: Vec<u64>,
+    /// seconds since startup and distance in cm, oldest first
+    ultra: VecDeque<(f64, f64)>,
+    /// a copy of `ultra` while the chart is paused
+    ultra_frozen: Option<Vec<(f64, f64)>>,
+    /// number of readings shown on the chart
+    ultra_window: usize,
+    guard: CollisionGuard,
+    /// the recording played instead of talking to a robot
+    replay: Option<Replay>,
+
+    /// where the robot is, from the simulator or guessed from the wheel speeds
+    pose: Pose,
+    /// where the robot has been, oldest first
+    trail: VecDeque<(f64, f64)>,
+    /// guesses the pose from the wheel speeds last sent when there's no simulator
+    odometry: Odometry,
+    /// the simulator's map, its view feed also gives the pose
+    map: Option<SimMap>,
+    /// camera servo angle the simulator reported
+    sim_servo: f64,
+    /// when the pose was last moved by dead reckoning
+    reckoned: Option<Instant>,
+
+    gpio: Vec<Pin>,
+    /// index of the selected pin
+    gpio_sel: usize,
+
+    periph: Peripherals,
+    /// titles of the LED, buzzer and servo panels, with the keys bound to them
+    periph_titles: [String; 3],
+}
+#[derive(Debug)]
+enum HistEntry {
+    /// a command with its return value or error
+    Cmd(String, Result<Option<String>, String>),
+    /// a value from a subscription, `at` is measured from startup
+    Event {
+        at: Duration,
+        id: u32,
+        value: String,
+    },
+}
+/// Ctrl-R reverse search through the typed commands
+#[derive(Debug)]
+struct Search {
+    query: String,
+    /// index of the match in the history
+    found: Option<usize>,
+    /// the input before searching, restored on `Esc`
+    original: String,
+}
+/// Hold-to-drive: the WASD keys only drive while they're held down
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Hold {
+    /// the terminal reports key releases, the repeat timeout is kept in case one goes missing
+    Release(Duration),
+    /// no release events, a key counts as held until it stops repeating for this long
+    Repeat(Duration),
 }
 #[derive(Debug, Clone)]
 pub enum Msg {
     Term(crossterm::event::Event),
     Roblib(roblib_client::roblib::event::ConcreteValue),
+    Tick,
+    /// a value from one of the user's subscriptions
+    Event(u32, ConcreteValue),
+    /// a new connection was made after the old one dropped
+    Reconnected,
+    /// the level of an input pin on the GPIO tab changed
+    Gpio(u8, bool),
+    Gamepad(Pad),
+    /// a line of roblib-sim's view feed
+    Sim(SimView),
+    /// roblib-sim's view feed ended, the pose is dead reckoned again
+    SimEnded,
+    /// wheel speeds of a replayed drive command
+    Wheels(f64, f64),
+    /// the watchdog stopped the robot after no input for too long
+    WatchdogStop,
 }
 
 impl TUI {
-    pub async fn new(robot: Robot) -> Result<Self> {
+    /// `hold` enables hold-to-drive, with the key repeat timeout in case a release isn't
+    /// reported, `record` starts recording the session to a file right away
+    #[allow(clippy::too_many_arguments)]
+    pub async fn new(
+        conn: Arc<Conn>,
+        hold: Option<Duration>,
+        watchdog: Arc<Mutex<Watchdog>>,
+        ultra: UltraOpts,
+        record: Option<PathBuf>,
+        replay: Option<Replay>,
+        config: &Config,
+    ) -> Result<Self> {
+        let keys = Keymap::new(&config.keys)?;
+        let recorder = record
+            .as_deref()
+            .map(|path| Recorder::create(path, false))
+            .transpose()?;
         let mut s = State::default();
-        s.ultra = vec![0; 200];
+        s.gpio = config.gpio.iter().map(Pin::new).collect();
+        s.guard = CollisionGuard::new(ultra.threshold / 100.);
+        s.guard.enabled = ultra.guard;
+        s.history = History::load();
+        s.ultra_window = ULTRA_SAMPLES;
+        s.replay = replay;
+        s.odometry = Odometry::new(config.odometry.calibration()?);
+        s.periph_titles = [
+            format!(
+                "LED ({}, {})",
+                keys.hint(Action::Led),
+                keys.hint(Action::LedColor)
+            ),
+            format!(
+                "Buzzer ({}, {} {})",
+                keys.hint(Action::Buzzer),
+                keys.hint(Action::ToneDown),
+                keys.hint(Action::ToneUp)
+            ),
+            format!(
+                "Servo ({}, {})",
+                keys.hint(Action::ServoLeft),
+                keys.hint(Action::ServoRight)
+            ),
+        ];
+
+        let enhanced = hold.is_some() && supports_keyboard_enhancement()?;
+        s.hold = hold.map(|t| {
+            if enhanced {
+                Hold::Release(t)
+            } else {
+                Hold::Repeat(t)
+            }
+        });
+
+        let (tx, _) = tokio::sync::broadcast::channel(1024);
+        let sink = tx.clone();
+        let mut subs = Subs::new(Arc::new(move |id, v| sink.send(Msg::Event(id, v)).is_ok()));
+        // see `subscribe` and `setup_gpio`
+        subs.reserve(ConcreteType::TrackSensor(event::TrackSensor));
+        subs.reserve(ConcreteType::UltraSensor(event::UltraSensor(
+            ultra.interval,
+        )));
+        for pin in s.gpio.iter().filter(|p| p.kind == PinKind::Input) {
+            subs.reserve(ConcreteType::GpioPin(event::GpioPin(pin.pin)));
+        }
 
         Ok(Self {
-            term: setup_terminal()?,
-            robot,
+            term: setup_terminal(enhanced)?,
+            enhanced,
+            restored: false,
+            conn,
+            tx,
+            subs,
+            started: Instant::now(),
+            mixer: DriveMixer::default(),
+            watchdog,
+            ultra_interval: ultra.interval,
+            keys,
+            areas: Areas::default(),
+            recorder,
+            record_path: record,
             s,
         })
     }
 
-    pub fn spawn(mut self) -> (JoinHandle<Result<()>>, JoinHandle<Result<()>>) {
-        let (tx, mut rx) = tokio::sync::broadcast::channel::<Msg>(1024);
+    /// Start the UI and input tasks, they stop on `cancel` and trigger it when they end
+    pub fn spawn(
+        mut self,
+        cancel: CancellationToken,
+    ) -> (JoinHandle<Result<()>>, JoinHandle<Result<()>>) {
+        let tx = self.tx.clone();
+        let rx = tx.subscribe();
 
+        let cancel_run = cancel.clone();
         let h1 = tokio::spawn(async move {
-            let mut track_rx = self
-                .robot
-                .subscribe(roblib_client::roblib::event::TrackSensor)
-                .await?;
-            let mut ultra_rx = self
-                .robot
-                .subscribe(roblib_client::roblib::event::UltraSensor(
-                    Duration::from_millis(100),
-                ))
-                .await?;
+            let _cancel = cancel_run.clone().drop_guard();
+            let res = self.run(rx, cancel_run).await;
+            let shutdown = self.shutdown().await;
+            res.and(shutdown)
+        });
 
-            self.term.clear()?;
-            loop {
-                self.render()?;
-                let msg = tokio::select! {
-                    Ok(msg) = rx.recv() => msg,
-                    Ok(t) = track_rx.recv() => Msg::Roblib(ConcreteValue::TrackSensor(t)),
-                    Ok(u) = ultra_rx.recv() => Msg::Roblib(ConcreteValue::UltraSensor(u)),
-                };
-                match msg {
-                    Msg::Term(crossterm::event::Event::Key(key)) => {
-                        // first close err popup
-                        if self.s.show_err.is_some() {
-                            self.s.show_err.take();
-                            continue;
-                        }
+        let h2 = tokio::spawn(event_listener(tx.clone(), cancel));
 
-                        // global unescapable control binds
-                        match key.code {
-                            // Exit application on `Ctrl-C`
-                            KeyCode::Char('c') | KeyCode::Char('C')
-                                if key.modifiers == KeyModifiers::CONTROL =>
-                            {
-                                restore_terminal(&mut self.term)?;
-                                println!("Bye!");
-                                std::process::exit(0)
-                            }
-                            KeyCode::Char('?') => {
-                                self.s.show_help = !self.s.show_help;
-                                continue;
-                            }
-                            KeyCode::Tab => {
-                                self.s.index = (self.s.index + 1) % TABS.len();
-                                continue;
-                            }
-                            KeyCode::BackTab => {
-                                if self.s.index > 0 {
-                                    self.s.index -= 1;
-                                } else {
-                                    self.s.index = TABS.len() - 1;
-                                }
-                                continue;
-                            }
-                            _ => (),
-                        }
+        (h1, h2)
+    }
 
-                        if self.s.index == 2 {
-                            match key.code {
-                                KeyCode::Enter => {
-                                    let s = self.s.input.value().trim();
-                                    let cmd =
-                                        match roblib_client::roblib::text_format::de::from_str(s) {
-                                            Ok(cmd) => cmd,
-                                            Err(e) => {
-                                                self.s.show_err = Some((e.to_string(), true));
-                                                self.s.input.reset();
-                                                continue;
-                                            }
-                                        };
-                                    let s = s.to_owned();
-                                    self.s.input.reset();
-                                    let res = crate::execute(cmd, &self.robot.transport).await?;
-                                    let mut hist = (s, None);
-                                    if let Some(s) = res {
-                                        hist.1 = Some(s);
-                                    }
-                                    self.s.cmd_hist.push(hist);
-                                    continue;
-                                }
-                                _ => {
-                                    self.s
-                                        .input
-                                        .handle_event(&crossterm::event::Event::Key(key));
-                                }
-                            };
-                        }
-                        match key.code {
-                            KeyCode::Char('Q') => {
-                                restore_terminal(&mut self.term)?;
-                                println!("Bye!");
-                                std::process::exit(0)
-                            }
-                            KeyCode::Up => {
-                                if self.s.speed + 5. <= 100. {
-                                    self.s.speed += 5.;
-                                }
-                            }
-                            KeyCode::Down => {
-                                if self.s.speed - 5. >= 0. {
-                                    self.s.speed -= 5.;
-                                }
-                            }
+    /// Sender for messages to the UI task
+    pub fn tx(&self) -> Tx {
+        self.tx.clone()
+    }
 
-                            KeyCode::Char('w') | KeyCode::Char('W') => {
-                                self.s.drive[0] = !self.s.drive[0];
-                                self.s.redrive = true;
-                                // self.robot.drive(1., 1.).await?;
-                            }
-                            KeyCode::Char('a') | KeyCode::Char('A') => {
-                                self.s.drive[1] = !self.s.drive[1];
-                                // self.robot.drive(-1., 1.).await?;
-                            }
-                            KeyCode::Char('s') | KeyCode::Char('S') => {
-                                self.s.drive[2] = !self.s.drive[2];
-                                // self.robot.drive(-1., -1.).await?;
-                            }
-                            KeyCode::Char('d') | KeyCode::Char('D') => {
-                                self.s.drive[3] = !self.s.drive[3];
-                                // self.robot.drive(1., -1.).await?;
-                            }
-                            KeyCode::Char(' ') => {
-                                self.s.drive = Default::default();
-                                self.robot.stop().await?;
-                            }
-                            _ => (),
+    async fn run(&mut self, mut rx: Receiver<Msg>, cancel: CancellationToken) -> Result<()> {
+        self.subscribe().await;
+        self.setup_gpio().await;
+
+        let mut tick = tokio::time::interval(Duration::from_millis(50));
+
+        self.term.clear()?;
+        loop {
+            if self.s.redrive {
+                self.s.redrive = false;
+                let speed = self.s.speed / 100.;
+                let cmd = match self.s.analog {
+                    Some((left, right)) => Some((left * speed, right * speed)),
+                    None => self.mixer.wasd(self.s.drive, speed),
+                }
+                .map(|(left, right)| self.s.guard.filter(left, right));
+                self.watchdog.lock().unwrap().drive(cmd);
+                if let Some(robot) = self.conn.robot() {
+                    let res = match cmd {
+                        Some((left, right)) => robot.drive(left, right).await,
+                        None => robot.stop().await,
+                    };
+                    self.conn.check(res);
+                    let (left, right) = cmd.unwrap_or_default();
+                    self.s.odometry.drive(left, right);
+                    self.record(Record::Cmd {
+                        cmd: match cmd {
+                            Some((left, right)) => format!("drive {left:.2} {right:.2}"),
+                            None => "stop".to_owned(),
+                        },
+                    });
+                }
+            }
+
+            self.render()?;
+            let msg = tokio::select! {
+                _ = cancel.cancelled() => return Ok(()),
+                Ok(msg) = rx.recv() => msg,
+                _ = tick.tick() => Msg::Tick,
+            };
+            if let Msg::Term(_) | Msg::Gamepad(_) = msg {
+                self.watchdog.lock().unwrap().feed();
+            }
+            match msg {
+                Msg::Term(crossterm::event::Event::Key(key))
+                    if key.kind == KeyEventKind::Release =>
+                {
+                    // even on the Cmd Terminal tab, a key held down while switching to it must
+                    // still stop driving when it's let go
+                    if let Some(i) = self.keys.get(&key).and_then(Action::drive_index) {
+                        self.drive_key(i, false);
+                    }
+                }
+                Msg::Term(crossterm::event::Event::Key(key)) => {
+                    // first close err popup
+                    if self.s.show_err.is_some() {
+                        self.s.show_err.take();
+                        continue;
+                    }
+
+                    // Exit application on `Ctrl-C`, this one can't be rebound
+                    if matches!(key.code, KeyCode::Char('c') | KeyCode::Char('C'))
+                        && key.modifiers == KeyModifiers::CONTROL
+                    {
+                        return Ok(());
+                    }
+
+                    // global control binds
+                    let action = self.keys.get(&key);
+                    if let Some(a @ (Action::Help | Action::NextTab | Action::PrevTab)) = action {
+                        self.action(a).await;
+                        continue;
+                    }
+
+                    if self.s.index == 2 {
+                        self.cmdterm_key(key).await;
+                        // don't let typing drive the robot
+                        continue;
+                    }
+                    if self.s.index == 3 && self.gpio_key(key.code).await {
+                        continue;
+                    }
+                    if self.s.index == 1 && self.ultra_key(key.code) {
+                        continue;
+                    }
+                    if self.s.index == 4 && key.code == KeyCode::Home {
+                        self.s.odometry.reset(Pose::default());
+                        self.s.pose = Pose::default();
+                        self.s.trail.clear();
+                        continue;
+                    }
+                    // the peripherals panel and its keys are only on the Main tab
+                    if action.is_some_and(Action::is_periph) && self.s.index != 0 {
+                        continue;
+                    }
+                    if let Some(action) = action {
+                        if self.action(action).await {
+                            return Ok(());
                         }
                     }
-                    Msg::Roblib(ConcreteValue::TrackSensor(t)) => {
-                        self.s.track = t;
+                }
+                Msg::Term(crossterm::event::Event::Mouse(m)) => self.mouse(m),
+                Msg::Sim(SimView::Map(map)) => {
+                    self.s.map = Some(map);
+                    self.s.trail.clear();
+                }
+                Msg::Sim(SimView::Pose {
+                    x,
+                    y,
+                    heading,
+                    servo,
+                }) => {
+                    self.s.sim_servo = servo;
+                    self.move_to(Pose::new(x, y, heading));
+                }
+                Msg::SimEnded => {
+                    // carry on from where the simulator last put it
+                    self.s.map = None;
+                    self.s.odometry.reset(self.s.pose);
+                }
+                Msg::Wheels(left, right) => self.s.odometry.drive(left, right),
+                Msg::WatchdogStop => {
+                    self.s.odometry.drive(0., 0.);
+                    self.record(Record::Cmd {
+                        cmd: "stop".to_owned(),
+                    });
+                }
+                Msg::Gamepad(Pad::Drive(drive)) => {
+                    if self.s.analog != drive {
+                        self.s.analog = drive;
+                        self.s.redrive = true;
+                    }
+                }
+                Msg::Gamepad(Pad::Action(action)) => {
+                    // unlike their keys, buttons work the peripherals from any tab
+                    if self.action(action).await {
+                        return Ok(());
+                    }
+                }
+                Msg::Roblib(ConcreteValue::TrackSensor(t)) => {
+                    self.s.track = t;
+                    self.record(Record::Track { sensors: t });
+                }
+                Msg::Roblib(ConcreteValue::UltraSensor(u)) => {
+                    self.record(Record::Ultra { distance: u });
+                    if self.s.ultra.len() == ULTRA_SAMPLES {
+                        self.s.ultra.pop_front();
+                    }
+                    let at = self.started.elapsed().as_secs_f64();
+                    self.s.ultra.push_back((at, u * 100.));
+                    // stop or resume driving forward
+                    if self.s.guard.update(u) {
+                        self.s.redrive = true;
+                    }
+                }
+                Msg::Event(id, v) => {
+                    let value = format!("{v:?}");
+                    self.record(Record::Event {
+                        id,
+                        value: value.clone(),
+                    });
+                    self.s.cmd_hist.push(HistEntry::Event {
+                        at: self.started.elapsed(),
+                        id,
+                        value,
+                    });
+                }
+                Msg::Gpio(pin, level) => {
+                    self.record(Record::Gpio { pin, level });
+                    for p in self.s.gpio.iter_mut().filter(|p| p.pin == pin) {
+                        p.level = Some(level);
+                    }
+                }
+                Msg::Reconnected => {
+                    self.subscribe().await;
+                    self.setup_gpio().await;
+                    if let Some(robot) = self.conn.robot() {
+                        let res = self.subs.resubscribe(&robot).await;
+                        self.conn.check(res);
+                    }
+                    // don't pick up driving where the old connection left off
+                    self.s.drive = Default::default();
+                    self.s.redrive = true;
+                }
+                Msg::Tick => {
+                    self.expire_held();
+                    self.dead_reckon();
+                    if let Some(replay) = &mut self.s.replay {
+                        for entry in replay.tick() {
+                            play(&self.tx, entry);
+                        }
+                    }
+                    // the watchdog already stopped the robot, show it
+                    if self.watchdog.lock().unwrap().tripped() {
+                        self.s.drive = Default::default();
+                        self.s.analog = None;
+                    }
+                }
+
+                _ => (),
+            }
+        }
+    }
+
+    /// A key pressed in the command terminal tab
+    async fn cmdterm_key(&mut self, key: KeyEvent) {
+        if self.search_key(key) {
+            return;
+        }
+
+        match key.code {
+            KeyCode::Char('r') if key.modifiers == KeyModifiers::CONTROL => {
+                self.s.search = Some(Search {
+                    query: String::new(),
+                    found: None,
+                    original: self.s.input.value().to_owned(),
+                });
+            }
+            KeyCode::Enter => match self.s.hist_sel.take() {
+                Some(i) => {
+                    // events can't be run again
+                    if let HistEntry::Cmd(line, _) = &self.s.cmd_hist[i] {
+                        let line = line.clone();
+                        self.eval(line).await;
                     }
-                    Msg::Roblib(ConcreteValue::UltraSensor(u)) => {
-                        self.s.ultra.pop();
-                        self.s.ultra.insert(0, (u * 1000.) as u64);
+                }
+                None => self.submit().await,
+            },
+            KeyCode::Up => {
+                if let Some(line) = self.s.history.prev(self.s.input.value()) {
+                    self.s.input = Input::new(line.to_owned());
+                }
+            }
+            KeyCode::Down => {
+                if let Some(line) = self.s.history.next() {
+                    self.s.input = Input::new(line.to_owned());
+                }
+            }
+            KeyCode::PageDown => self.select_hist(true),
+            KeyCode::PageUp => self.select_hist(false),
+            KeyCode::Esc => {
+                self.s.hist_sel = None;
+                self.s.history.reset();
+            }
+            _ => {
+                let changed = self
+                    .s
+                    .input
+                    .handle_event(&crossterm::event::Event::Key(key));
+                // an edited line is a new one, Up starts from the newest again
+                if changed.is_some_and(|c| c.value) {
+                    self.s.history.reset();
+                }
+            }
+        }
+    }
+
+    /// Move the selection in the command history, the list is newest first, so down goes back
+    /// in time
+    fn select_hist(&mut self, older: bool) {
+        self.s.hist_sel = if older {
+            match self.s.hist_sel {
+                None => self.s.cmd_hist.len().checked_sub(1),
+                Some(i) => Some(i.saturating_sub(1)),
+            }
+        } else {
+            self.s
+                .hist_sel
+                .map(|i| i + 1)
+                .filter(|&i| i < self.s.cmd_hist.len())
+        };
+    }
+
+    fn mouse(&mut self, m: MouseEvent) {
+        let (x, y) = (m.column, m.row);
+        match m.kind {
+            MouseEventKind::Down(MouseButton::Left) => {
+                if self.s.show_err.take().is_some() {
+                    return;
+                }
+                if hit(self.areas.tabs, x, y) {
+                    if let Some(i) = tab_at(self.areas.tabs, x) {
+                        self.s.index = i;
                     }
+                } else if let Some(i) = drive_key_at(self.areas.controls, x, y) {
+                    self.s.mouse_key = Some(i);
+                    self.drive_key(i, true);
+                } else if hit(self.areas.speed, x, y) {
+                    self.s.mouse_speed = true;
+                    self.speed_at(x);
+                }
+            }
+            MouseEventKind::Drag(MouseButton::Left) if self.s.mouse_speed => self.speed_at(x),
+            MouseEventKind::Up(MouseButton::Left) => {
+                self.s.mouse_speed = false;
+                if let Some(i) = self.s.mouse_key.take() {
+                    self.drive_key(i, false);
+                }
+            }
+            MouseEventKind::ScrollDown if hit(self.areas.history, x, y) => self.select_hist(true),
+            MouseEventKind::ScrollUp if hit(self.areas.history, x, y) => self.select_hist(false),
+            // zoom the chart in and out
+            MouseEventKind::ScrollUp if hit(self.areas.chart, x, y) => {
+                self.s.ultra_window = self.s.ultra_window.saturating_sub(20).max(20);
+            }
+            MouseEventKind::ScrollDown if hit(self.areas.chart, x, y) => {
+                self.s.ultra_window = (self.s.ultra_window + 20).min(ULTRA_SAMPLES);
+            }
+            _ => (),
+        }
+    }
 
-                    _ => (),
+    /// Set the speed from where the speed gauge was clicked, in steps of 5 like the keys
+    fn speed_at(&mut self, x: u16) {
+        let r = self.areas.speed;
+        let ratio = x.saturating_sub(r.x) as f64 / r.width.saturating_sub(1).max(1) as f64;
+        let speed = ((ratio * 20.).round() * 5.).clamp(0., 100.);
+        if speed != self.s.speed {
+            self.s.speed = speed;
+            self.s.redrive = true;
+        }
+    }
+
+    /// Handle a key while searching, returns `false` if it should be handled as usual
+    fn search_key(&mut self, key: KeyEvent) -> bool {
+        let Some(search) = &mut self.s.search else {
+            return false;
+        };
+        let history = &self.s.history;
+
+        match key.code {
+            // an older match
+            KeyCode::Char('r') if key.modifiers == KeyModifiers::CONTROL => {
+                if let Some(i) = history.search(&search.query, search.found) {
+                    search.found = Some(i);
                 }
+            }
+            KeyCode::Char(c) => {
+                search.query.push(c);
+                search.found = history.search(&search.query, None);
+            }
+            KeyCode::Backspace => {
+                search.query.pop();
+                search.found = history.search(&search.query, None);
+            }
+            KeyCode::Esc => {
+                self.s.input = Input::new(std::mem::take(&mut search.original));
+                self.s.search = None;
+                return true;
+            }
+            // keep the match, `Enter` runs it right away
+            _ => {
+                self.s.search = None;
+                return false;
+            }
+        }
 
-                if self.s.redrive {
-                    // TODO: drive
+        if let Some(line) = search.found.and_then(|i| history.get(i)) {
+            self.s.input = Input::new(line.to_owned());
+        }
+        true
+    }
+
+    /// Run the line typed into the command terminal
+    async fn submit(&mut self) {
+        let line = self.s.input.value().trim().to_owned();
+        self.s.input.reset();
+        if line.is_empty() {
+            return;
+        }
+        self.eval(line).await;
+    }
+
+    /// Run a command line and add it to the history
+    async fn eval(&mut self, line: String) {
+        self.s.history.push(&line);
+        self.record(Record::Cmd { cmd: line.clone() });
+
+        let robot = self.conn.robot();
+        let res = dispatch::eval(
+            &line,
+            robot.as_deref(),
+            &mut self.subs,
+            &mut self.s.odometry,
+        )
+        .await
+        .map_err(|e| e.to_string());
+        if let Err(e) = &res {
+            self.s.show_err = Some((e.clone(), true));
+        }
+        self.s.cmd_hist.push(HistEntry::Cmd(line, res));
+    }
+
+    /// Pause the chart and move the threshold in the ultra sensor tab, returns `false` if the key
+    /// wasn't for the tab
+    fn ultra_key(&mut self, code: KeyCode) -> bool {
+        match code {
+            KeyCode::Char('p') | KeyCode::Char('P') => {
+                self.s.ultra_frozen = match self.s.ultra_frozen {
+                    Some(_) => None,
+                    None => Some(self.s.ultra.iter().copied().collect()),
+                };
+            }
+            KeyCode::Char('+') => {
+                self.s.guard.threshold += 0.05;
+                self.s.redrive = true;
+            }
+            KeyCode::Char('-') => {
+                self.s.guard.threshold = (self.s.guard.threshold - 0.05).max(0.);
+                self.s.redrive = true;
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// Do what a key or gamepad button is bound to, returns `true` to quit
+    async fn action(&mut self, action: Action) -> bool {
+        if let Some(i) = action.drive_index() {
+            self.drive_key(i, true);
+            return false;
+        }
+        if self.periph_key(action).await {
+            return false;
+        }
+
+        match action {
+            Action::Quit => return true,
+            Action::Help => self.s.show_help = !self.s.show_help,
+            Action::NextTab => self.s.index = (self.s.index + 1) % TABS.len(),
+            Action::PrevTab => {
+                if self.s.index > 0 {
+                    self.s.index -= 1;
+                } else {
+                    self.s.index = TABS.len() - 1;
+                }
+            }
+            Action::SpeedUp => {
+                if self.s.speed + 5. <= 100. {
+                    self.s.speed += 5.;
+                    self.s.redrive = true;
+                }
+            }
+            Action::SpeedDown => {
+                if self.s.speed - 5. >= 0. {
+                    self.s.speed -= 5.;
+                    self.s.redrive = true;
                 }
             }
-        });
 
-        let h2 = tokio::spawn(event_listener(tx.clone()));
+            Action::Stop => {
+                self.s.drive = Default::default();
+                self.s.analog = None;
+                self.s.redrive = true;
+            }
+            Action::Guard => {
+                self.s.guard.enabled = !self.s.guard.enabled;
+                self.s.redrive = true;
+            }
+            Action::Record => self.toggle_record(),
+            Action::ReplayPause => {
+                if let Some(replay) = &mut self.s.replay {
+                    replay.paused = !replay.paused;
+                }
+            }
+            Action::ReplayStep => {
+                if let Some(entry) = self.s.replay.as_mut().and_then(Replay::step) {
+                    play(&self.tx, entry);
+                }
+            }
+            Action::ReplayBack | Action::ReplayForward => {
+                let steps = if action == Action::ReplayBack {
+                    -1.
+                } else {
+                    1.
+                };
+                if let Some(replay) = &mut self.s.replay {
+                    // the chart's times would jump
+                    self.s.ultra.clear();
+                    for entry in replay.seek(steps) {
+                        play(&self.tx, entry);
+                    }
+                    self.reckon_replay();
+                }
+            }
+            _ => (),
+        }
+        false
+    }
 
-        (h1, h2)
+    /// Move the pose along with the wheel speeds, unless the simulator says where the robot is
+    fn dead_reckon(&mut self) {
+        let now = Instant::now();
+        let Some(last) = self.s.reckoned.replace(now) else {
+            return;
+        };
+        if self.s.map.is_some() {
+            return;
+        }
+        // replays move in their own time
+        let rate = match &self.s.replay {
+            Some(r) if r.paused || r.finished() => 0.,
+            Some(r) => r.speed,
+            None => 1.,
+        };
+        self.s.odometry.advance((now - last).as_secs_f64() * rate);
+        self.move_to(self.s.odometry.pose());
+    }
+
+    /// Dead reckon the replay from its start to where it is now, as if it had played through.
+    /// Seeking skips the drive commands in between, which would leave the pose and wheels behind.
+    fn reckon_replay(&mut self) {
+        let Some(replay) = &self.s.replay else {
+            return;
+        };
+        let (drives, end) = (replay.drives(), replay.position());
+        self.s.odometry.reset(Pose::default());
+        self.s.odometry.drive(0., 0.);
+        if self.s.map.is_none() {
+            self.s.pose = Pose::default();
+            self.s.trail.clear();
+        }
+
+        let mut at = 0.;
+        for (next, (left, right)) in drives {
+            self.reckon_for(next - at);
+            at = next;
+            self.s.odometry.drive(left, right);
+        }
+        self.reckon_for(end - at);
+    }
+
+    /// Advance the odometry by `secs` in small steps, so the trail follows the turns
+    fn reckon_for(&mut self, mut secs: f64) {
+        while secs > 0. {
+            let dt = secs.min(RECKON_STEP);
+            self.s.odometry.advance(dt);
+            secs -= dt;
+            if self.s.map.is_none() {
+                self.move_to(self.s.odometry.pose());
+            }
+        }
+    }
+
+    fn move_to(&mut self, pose: Pose) {
+        self.s.pose = pose;
+        if self.s.trail.back() != Some(&(pose.x, pose.y)) {
+            if self.s.trail.len() == TRAIL_LEN {
+                self.s.trail.pop_front();
+            }
+            self.s.trail.push_back((pose.x, pose.y));
+        }
+    }
+
+    /// Start recording the session, or stop and tell where it was saved
+    fn toggle_record(&mut self) {
+        if let Some(recorder) = self.recorder.take() {
+            let msg = format!("Recording saved to {}", recorder.path().display());
+            self.s.show_err = Some((msg, false));
+            return;
+        }
+
+        // the file given with `--record` collects every recording of the session
+        let (path, append) = match &self.record_path {
+            Some(path) => (Some(path.clone()), true),
+            None => {
+                let secs = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs();
+                (config::data_file(&format!("session-{secs}.jsonl")), false)
+            }
+        };
+        let Some(path) = path else {
+            self.s.show_err = Some(("No data directory to record to".to_owned(), true));
+            return;
+        };
+        match Recorder::create(&path, append) {
+            Ok(recorder) => self.recorder = Some(recorder),
+            Err(e) => self.s.show_err = Some((format!("{e:#}"), true)),
+        }
+    }
+
+    /// Add an entry to the recording if there is one, it's stopped if writing fails
+    fn record(&mut self, record: Record) {
+        let Some(recorder) = &mut self.recorder else {
+            return;
+        };
+        let entry = Entry {
+            at: self.started.elapsed().as_secs_f64(),
+            record,
+        };
+        if let Err(e) = recorder.write(&entry) {
+            self.s.show_err = Some((format!("Recording stopped: {e}"), true));
+            self.recorder = None;
+        }
+    }
+
+    /// Control the LED, buzzer and servo, returns `false` if the action isn't for them
+    async fn periph_key(&mut self, action: Action) -> bool {
+        let mut next = self.s.periph;
+        let device = match action {
+            Action::Led => {
+                next.led = !next.led;
+                Device::Led
+            }
+            Action::LedColor => {
+                next.next_color();
+                next.led = true;
+                Device::Led
+            }
+            Action::Buzzer => {
+                next.buzzer = !next.buzzer;
+                Device::Buzzer
+            }
+            Action::ToneDown => {
+                next.adjust_tone(-1.);
+                Device::Buzzer
+            }
+            Action::ToneUp => {
+                next.adjust_tone(1.);
+                Device::Buzzer
+            }
+            Action::ServoLeft => {
+                next.adjust_servo(-1.);
+                Device::Servo
+            }
+            Action::ServoRight => {
+                next.adjust_servo(1.);
+                Device::Servo
+            }
+            _ => return false,
+        };
+
+        // only show what the robot actually got
+        let res = match self.conn.robot() {
+            Some(robot) => next.send(&robot, device).await,
+            None => Err(anyhow::anyhow!("Not connected")),
+        };
+        match res {
+            Ok(()) => {
+                self.s.periph = next;
+                self.record(Record::Cmd {
+                    cmd: next.describe(device),
+                });
+            }
+            Err(e) => self.s.show_err = Some((e.to_string(), true)),
+        }
+        true
+    }
+
+    /// Handle a key on the GPIO tab, returns `false` if it wasn't for the tab
+    async fn gpio_key(&mut self, code: KeyCode) -> bool {
+        let len = self.s.gpio.len();
+        let Some(pin) = self.s.gpio.get_mut(self.s.gpio_sel) else {
+            return false;
+        };
+        let changed = match code {
+            KeyCode::Up => {
+                self.s.gpio_sel = (self.s.gpio_sel + len - 1) % len;
+                return true;
+            }
+            KeyCode::Down => {
+                self.s.gpio_sel = (self.s.gpio_sel + 1) % len;
+                return true;
+            }
+            KeyCode::Enter => pin.toggle(),
+            KeyCode::Left => pin.adjust(-1.),
+            KeyCode::Right => pin.adjust(1.),
+            KeyCode::Char('+') => pin.scale_hz(true),
+            KeyCode::Char('-') => pin.scale_hz(false),
+            _ => return false,
+        };
+
+        if let (true, Some(robot)) = (changed, self.conn.robot()) {
+            match pin.apply(&robot).await {
+                Ok(()) => {
+                    if let Some(cmd) = pin.describe() {
+                        self.record(Record::Cmd { cmd });
+                    }
+                }
+                Err(e) => self.s.show_err = Some((e.to_string(), true)),
+            }
+        }
+        true
+    }
+
+    /// Set up the pins on the GPIO tab and follow the inputs
+    async fn setup_gpio(&mut self) {
+        let Some(robot) = self.conn.robot() else {
+            return;
+        };
+        for pin in &mut self.s.gpio {
+            if let Err(e) = pin.setup(&robot).await {
+                self.s.show_err = Some((format!("Failed to set up pin {}: {e}", pin.pin), true));
+                continue;
+            }
+            if pin.kind != PinKind::Input {
+                continue;
+            }
+
+            let (id, tx) = (pin.pin, self.tx.clone());
+            match robot.subscribe(event::GpioPin(id)).await {
+                Ok(mut rx) => {
+                    tokio::spawn(async move {
+                        while let Ok(level) = rx.recv().await {
+                            if tx.send(Msg::Gpio(id, level)).is_err() {
+                                break;
+                            }
+                        }
+                    });
+                }
+                Err(e) => self.s.show_err = Some((e.to_string(), true)),
+            }
+        }
+    }
+
+    /// Forward the sensor subscriptions of the current connection to the UI task
+    async fn subscribe(&self) {
+        let Some(robot) = self.conn.robot() else {
+            return;
+        };
+        let res = async {
+            let mut track_rx = robot.subscribe(event::TrackSensor).await?;
+            let mut ultra_rx = robot
+                .subscribe(event::UltraSensor(self.ultra_interval))
+                .await?;
+
+            // these end along with the connection
+            let tx = self.tx.clone();
+            tokio::spawn(async move {
+                while let Ok(t) = track_rx.recv().await {
+                    if tx.send(Msg::Roblib(ConcreteValue::TrackSensor(t))).is_err() {
+                        break;
+                    }
+                }
+            });
+            let tx = self.tx.clone();
+            tokio::spawn(async move {
+                while let Ok(u) = ultra_rx.recv().await {
+                    if tx.send(Msg::Roblib(ConcreteValue::UltraSensor(u))).is_err() {
+                        break;
+                    }
+                }
+            });
+            anyhow::Ok(())
+        }
+        .await;
+        self.conn.check(res);
+    }
+
+    /// Stop the robot, drop the subscriptions and give the terminal back
+    async fn shutdown(&mut self) -> Result<()> {
+        let robot = match self.conn.robot() {
+            Some(robot) => {
+                async {
+                    robot.stop().await?;
+                    if self.s.periph.buzzer {
+                        self.s.periph.buzzer = false;
+                        self.s.periph.send(&robot, Device::Buzzer).await?;
+                    }
+                    self.subs.unsubscribe_all(Some(&robot)).await?;
+                    robot.unsubscribe(event::TrackSensor).await?;
+                    robot
+                        .unsubscribe(event::UltraSensor(self.ultra_interval))
+                        .await?;
+                    for pin in self.s.gpio.iter().filter(|p| p.kind == PinKind::Input) {
+                        robot.unsubscribe(event::GpioPin(pin.pin)).await?;
+                    }
+                    anyhow::Ok(())
+                }
+                .await
+            }
+            None => Ok(()),
+        };
+        self.restore()?;
+        robot
+    }
+
+    fn restore(&mut self) -> Result<()> {
+        if !self.restored {
+            self.restored = true;
+            restore_terminal(&mut self.term, self.enhanced)?;
+        }
+        Ok(())
+    }
+
+    /// A drive key was pressed or released
+    fn drive_key(&mut self, i: usize, pressed: bool) {
+        let on = match self.s.hold {
+            None if !pressed => return,
+            None => !self.s.drive[i],
+            Some(_) => pressed,
+        };
+        if on {
+            self.s.held[i] = Some(Instant::now());
+        }
+        if self.s.drive[i] != on {
+            self.s.drive[i] = on;
+            self.s.redrive = true;
+        }
+    }
+
+    /// Release the drive keys that stopped repeating
+    fn expire_held(&mut self) {
+        let Some(Hold::Release(timeout) | Hold::Repeat(timeout)) = self.s.hold else {
+            return;
+        };
+        for i in 0..self.s.drive.len() {
+            let expired = self.s.held[i].is_none_or(|t| t.elapsed() > timeout);
+            if self.s.drive[i] && expired {
+                self.s.drive[i] = false;
+                self.s.redrive = true;
+            }
+        }
     }
 
     fn render(&mut self) -> Result<()> {
+        let status = self.conn.status();
+        self.areas = Areas::default();
         self.term.draw(|f| {
             let layout = Layout::default()
                 .direction(Direction::Vertical)
@@ -223,20 +1151,32 @@ impl TUI {
                 .constraints([Constraint::Percentage(10), Constraint::Percentage(10)].as_ref())
                 .split(f.size());
 
+            let top = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([Constraint::Min(0), Constraint::Max(24)])
+                .split(layout[0]);
+
             let tabs = Tabs::new(TABS.to_vec())
                 .block(Block::default().borders(Borders::ALL).title("Tabs"))
                 .select(self.s.index)
                 .highlight_style(
                     Style::default().add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
                 );
-            f.render_widget(tabs, layout[0]);
+            f.render_widget(tabs, top[0]);
+            self.areas.tabs = Block::default().borders(Borders::ALL).inner(top[0]);
 
-            [Self::render_main, Self::render_ultra, Self::render_cmdterm][self.s.index](
-                &self.s, f, layout[1],
-            );
+            Self::render_status(status, self.recorder.is_some(), f, top[1]);
+
+            [
+                Self::render_main,
+                Self::render_ultra,
+                Self::render_cmdterm,
+                Self::render_gpio,
+                Self::render_map,
+            ][self.s.index](&self.s, &mut self.areas, f, layout[1]);
 
             if self.s.show_help {
-                Self::render_help(f);
+                Self::render_help(&self.keys, self.s.replay.is_some(), f);
             }
 
             if let Some((msg, err)) = &self.s.show_err {
@@ -245,11 +1185,32 @@ impl TUI {
         })?;
         Ok(())
     }
-    fn render_main(s: &State, f: &mut Frame<impl Backend>, frame: Rect) {
+    fn render_status(status: Status, recording: bool, f: &mut Frame<impl Backend>, frame: Rect) {
+        let (text, color) = match status {
+            Status::Connected(Some(latency)) => (
+                format!("● connected {}ms", latency.as_millis()),
+                Color::Green,
+            ),
+            Status::Connected(None) => ("● connected".to_owned(), Color::Green),
+            Status::Reconnecting(0) => ("● reconnecting".to_owned(), Color::Yellow),
+            Status::Reconnecting(n) => (format!("● reconnecting ({n})"), Color::Yellow),
+            Status::Offline => ("● offline".to_owned(), Color::Red),
+            Status::Replay => ("● replay".to_owned(), Color::Blue),
+        };
+        let p = Paragraph::new(Span::styled(text, Style::default().fg(color)))
+            .block(Block::default().borders(Borders::ALL).title(if recording {
+                Span::styled("Robot (rec)", Style::default().fg(Color::Red))
+            } else {
+                Span::from("Robot")
+            }))
+            .alignment(Alignment::Center);
+        f.render_widget(p, frame);
+    }
+    fn render_main(s: &State, areas: &mut Areas, f: &mut Frame<impl Backend>, frame: Rect) {
         let layout = Layout::default()
             .direction(Direction::Vertical)
             .margin(1)
-            .constraints([Constraint::Max(3), Constraint::Min(0)])
+            .constraints([Constraint::Max(3), Constraint::Max(3), Constraint::Min(0)])
             .split(frame);
 
         {
@@ -270,17 +1231,41 @@ impl TUI {
                     spans[i].patch_style(Style::default().fg(c));
                 }
             }
+            if let Some((left, right)) = s.analog {
+                spans = vec![Span::styled(
+                    format!("{left:+.2} {right:+.2}"),
+                    Style::default().fg(c),
+                )];
+            }
+            let mut block = Block::default().borders(Borders::ALL);
+            block = if s.guard.blocking() {
+                block
+                    .title("Obstacle!")
+                    .border_style(Style::default().fg(Color::Red))
+            } else {
+                block.title("Controls")
+            };
             let controls = Paragraph::new(Line::from(spans))
-                .block(Block::default().borders(Borders::ALL).title("Controls"))
+                .block(block)
                 .alignment(Alignment::Center);
             f.render_widget(controls, layout[0]);
+            areas.controls = Block::default().borders(Borders::ALL).inner(layout[0]);
 
             let speed = Gauge::default()
-                .block(Block::default().borders(Borders::ALL).title("Speed"))
+                .block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .title(if s.guard.enabled {
+                            "Speed (guard on)"
+                        } else {
+                            "Speed (guard off)"
+                        }),
+                )
                 .gauge_style(Style::default().fg(c))
                 .use_unicode(true)
                 .ratio(s.speed / 100.);
             f.render_widget(speed, layout[1]);
+            areas.speed = Block::default().borders(Borders::ALL).inner(layout[1]);
 
             let track = Paragraph::new(Line::from(
                 s.track
@@ -299,29 +1284,200 @@ impl TUI {
             f.render_widget(track, layout[2]);
         }
 
-        let data = &s.ultra.iter().copied().rev().take(20).collect::<Vec<_>>();
+        {
+            let layout = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([
+                    Constraint::Percentage(30),
+                    Constraint::Percentage(30),
+                    Constraint::Percentage(40),
+                ])
+                .split(layout[1]);
+            let p = &s.periph;
+            let dim = Style::default().add_modifier(Modifier::DIM);
+
+            let led = if p.led {
+                Span::styled(
+                    format!("● {}", p.color_name()),
+                    Style::default().fg(p.color()),
+                )
+            } else {
+                Span::styled(format!("○ off ({})", p.color_name()), dim)
+            };
+            let led = Paragraph::new(led)
+                .block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .title(s.periph_titles[0].as_str()),
+                )
+                .alignment(Alignment::Center);
+            f.render_widget(led, layout[0]);
+
+            let tone = format!("tone {:.0}%", p.tone * 100.);
+            let buzzer = if p.buzzer {
+                Span::styled(format!("on, {tone}"), Style::default().fg(Color::Yellow))
+            } else {
+                Span::styled(format!("off, {tone}"), dim)
+            };
+            let buzzer = Paragraph::new(buzzer)
+                .block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .title(s.periph_titles[1].as_str()),
+                )
+                .alignment(Alignment::Center);
+            f.render_widget(buzzer, layout[1]);
+
+            let servo = Gauge::default()
+                .block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .title(s.periph_titles[2].as_str()),
+                )
+                .use_unicode(true)
+                .ratio((p.servo + 90.) / 180.)
+                .label(format!("{:.0}°", p.servo));
+            f.render_widget(servo, layout[2]);
+        }
+
+        let data = &s
+            .ultra
+            .iter()
+            .skip(s.ultra.len().saturating_sub(20))
+            .map(|(_, d)| (d * 10.) as u64)
+            .collect::<Vec<_>>();
         let spark = Sparkline::default()
             .data(data)
             .block(Block::default().borders(Borders::ALL).title("Ultra Sensor"));
         // let spark = Paragraph::new(format!("{:?}", data));
-        f.render_widget(spark, layout[1]);
+        match &s.replay {
+            Some(replay) => {
+                let layout = Layout::default()
+                    .direction(Direction::Horizontal)
+                    .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+                    .split(layout[2]);
+                f.render_widget(spark, layout[0]);
+                Self::render_replay(replay, f, layout[1]);
+            }
+            None => f.render_widget(spark, layout[2]),
+        }
+    }
+    /// Where the replay is and the commands that were sent up to there
+    fn render_replay(replay: &Replay, f: &mut Frame<impl Backend>, frame: Rect) {
+        let state = if replay.finished() {
+            "finished".to_owned()
+        } else if replay.paused {
+            "paused".to_owned()
+        } else {
+            format!("{}x", replay.speed)
+        };
+        let dim = Style::default().add_modifier(Modifier::DIM);
+
+        let mut text = vec![Line::from(format!(
+            "{:.1}s / {:.1}s, {state}",
+            replay.position(),
+            replay.duration()
+        ))];
+        let rows = frame.height.saturating_sub(3) as usize;
+        text.extend(replay.commands(rows).into_iter().map(|(at, cmd)| {
+            Line::from(vec![
+                Span::styled(format!("{at:>7.2}s "), dim),
+                Span::from(cmd.to_owned()),
+            ])
+        }));
+        let p = Paragraph::new(text).block(Block::default().borders(Borders::ALL).title("Replay"));
+        f.render_widget(p, frame);
     }
-    fn render_ultra(_s: &State, f: &mut Frame<impl Backend>, frame: Rect) {
+    fn render_ultra(s: &State, areas: &mut Areas, f: &mut Frame<impl Backend>, frame: Rect) {
         let layout = Layout::default()
             .direction(Direction::Vertical)
             .margin(1)
-            .constraints(
-                [
-                    Constraint::Percentage(10),
-                    // Constraint::Percentage(10),
-                    // Constraint::Percentage(10),
-                ]
-                .as_ref(),
-            )
+            .constraints([Constraint::Min(0), Constraint::Max(3)])
             .split(frame);
-        f.render_widget(Paragraph::new("Ultra"), layout[0])
+
+        let mut data = match &s.ultra_frozen {
+            Some(frozen) => frozen.clone(),
+            None => s.ultra.iter().copied().collect(),
+        };
+        data.drain(..data.len().saturating_sub(s.ultra_window));
+        let (start, end) = match (data.first(), data.last()) {
+            (Some((start, _)), Some((end, _))) if end > start => (*start, *end),
+            _ => (0., 1.),
+        };
+        let threshold = s.guard.threshold * 100.;
+        let top = data
+            .iter()
+            .map(|(_, d)| *d)
+            .fold(threshold, f64::max)
+            .max(1.)
+            * 1.1;
+        let limit = [(start, threshold), (end, threshold)];
+
+        let datasets = vec![
+            Dataset::default()
+                .name("distance")
+                .marker(symbols::Marker::Braille)
+                .graph_type(GraphType::Line)
+                .style(Style::default().fg(Color::Cyan))
+                .data(&data),
+            Dataset::default()
+                .name("threshold")
+                .marker(symbols::Marker::Braille)
+                .graph_type(GraphType::Line)
+                .style(Style::default().fg(Color::Red))
+                .data(&limit),
+        ];
+        let title = if s.ultra_frozen.is_some() {
+            "Distance (paused)"
+        } else {
+            "Distance"
+        };
+        let label = |v: f64| Span::from(format!("{v:.1}"));
+        let chart = Chart::new(datasets)
+            .block(Block::default().borders(Borders::ALL).title(title))
+            .x_axis(
+                Axis::default()
+                    .title("s")
+                    .bounds([start, end])
+                    .labels(vec![label(start), label(end)]),
+            )
+            .y_axis(Axis::default().title("cm").bounds([0., top]).labels(vec![
+                label(0.),
+                label(top / 2.),
+                label(top),
+            ]));
+        f.render_widget(chart, layout[0]);
+        areas.chart = layout[0];
+
+        let mut spans = match ultra_stats(&data) {
+            Some(st) => vec![
+                Span::from(format!(
+                    "min {:.1}  max {:.1}  mean {:.1}  stddev {:.1}  ",
+                    st.min, st.max, st.mean, st.stddev
+                )),
+                Span::styled(
+                    format!("last {:.1}", st.last),
+                    if st.last < threshold {
+                        Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)
+                    } else {
+                        Style::default()
+                    },
+                ),
+            ],
+            None => vec![Span::from("no readings")],
+        };
+        spans.push(Span::styled(
+            format!("  threshold {threshold:.0} (+/-)  pause (p)"),
+            Style::default().add_modifier(Modifier::DIM),
+        ));
+        let stats = Paragraph::new(Line::from(spans)).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Statistics (cm)"),
+        );
+        f.render_widget(stats, layout[1]);
     }
-    fn render_cmdterm(s: &State, f: &mut Frame<impl Backend>, frame: Rect) {
+    fn render_cmdterm(s: &State, areas: &mut Areas, f: &mut Frame<impl Backend>, frame: Rect) {
         let layout = Layout::default()
             .direction(Direction::Vertical)
             .margin(1)
@@ -332,9 +1488,16 @@ impl TUI {
 
         let width = layout[0].width.max(3) - 3; // keep 2 for borders and 1 for cursor
         let scroll = s.input.visual_scroll(width as usize);
+        let title = match &s.search {
+            Some(Search {
+                query, found: None, ..
+            }) if !query.is_empty() => format!("Failing search: {query}"),
+            Some(Search { query, .. }) => format!("Search: {query}"),
+            None => "Cmd Input".to_owned(),
+        };
         let inp = Paragraph::new(s.input.value())
             .scroll((0, scroll as u16))
-            .block(block.clone().title("Cmd Input"));
+            .block(block.clone().title(title));
         f.render_widget(inp, layout[0]);
         f.set_cursor(
             // Put cursor past the end of the input text
@@ -347,37 +1510,256 @@ impl TUI {
             .cmd_hist
             .iter()
             .rev()
-            .map(|s| {
-                ListItem::new(Line::from(match &s.1 {
-                    Some(ret) => format!("{} - {}", s.0, ret),
-                    None => s.0.clone(),
+            .map(|h| {
+                ListItem::new(Line::from(match h {
+                    HistEntry::Cmd(cmd, Ok(Some(ret))) => {
+                        vec![Span::from(format!("{cmd} - {ret}"))]
+                    }
+                    HistEntry::Cmd(cmd, Ok(None)) => vec![Span::from(cmd.clone())],
+                    HistEntry::Cmd(cmd, Err(e)) => vec![
+                        Span::styled("✗ ", Style::default().fg(Color::Red)),
+                        Span::from(format!("{cmd} - ")),
+                        Span::styled(e.clone(), Style::default().fg(Color::Red)),
+                    ],
+                    HistEntry::Event { at, id, value } => vec![
+                        Span::styled(
+                            format!("[{:>9.3}s] #{id} ", at.as_secs_f64()),
+                            Style::default().add_modifier(Modifier::DIM),
+                        ),
+                        Span::from(value.clone()),
+                    ],
                 }))
             })
             .collect();
         let list = List::new(list)
             .block(block.title("Command History"))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
             .start_corner(Corner::TopLeft);
-        f.render_widget(list, layout[1]);
+        // scrolls the list to keep the selection in view
+        let mut state = ListState::default();
+        state.select(s.hist_sel.map(|i| s.cmd_hist.len() - 1 - i));
+        f.render_stateful_widget(list, layout[1], &mut state);
+        areas.history = layout[1];
+    }
+    fn render_gpio(s: &State, areas: &mut Areas, f: &mut Frame<impl Backend>, frame: Rect) {
+        let layout = Layout::default()
+            .direction(Direction::Horizontal)
+            .margin(1)
+            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+            .split(frame);
+
+        if s.gpio.is_empty() {
+            let p = Paragraph::new("No pins, add them to the [[gpio]] list in the config file")
+                .block(Block::default().borders(Borders::ALL).title("Pins"))
+                .wrap(Wrap { trim: true });
+            f.render_widget(p, frame);
+            return;
+        }
+
+        let dim = Style::default().add_modifier(Modifier::DIM);
+        let items: Vec<_> = s
+            .gpio
+            .iter()
+            .map(|p| {
+                let value = match p.kind {
+                    PinKind::Input => match p.level {
+                        Some(true) => Span::styled("high", Style::default().fg(Color::Green)),
+                        Some(false) => Span::from("low"),
+                        None => Span::styled("?", dim),
+                    },
+                    PinKind::Output if p.on => {
+                        Span::styled("on", Style::default().fg(Color::Green))
+                    }
+                    PinKind::Output => Span::from("off"),
+                    PinKind::Pwm => Span::styled(
+                        format!("{:.0}% {}Hz", p.duty * 100., p.hz),
+                        if p.on { Style::default() } else { dim },
+                    ),
+                    PinKind::Servo => Span::from(format!("{:.0}°", p.angle)),
+                };
+                ListItem::new(Line::from(vec![
+                    Span::from(format!("{:>3} {:<12} ", p.pin, p.name)),
+                    Span::styled(format!("{:<7}", format!("{:?}", p.kind)), dim),
+                    value,
+                ]))
+            })
+            .collect();
+        let list = List::new(items)
+            .block(Block::default().borders(Borders::ALL).title("Pins"))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        let mut state = ListState::default();
+        state.select(Some(s.gpio_sel));
+        f.render_stateful_widget(list, layout[0], &mut state);
+
+        let pin = &s.gpio[s.gpio_sel];
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .title(format!("{} (pin {})", pin.name, pin.pin));
+        let (gauge, keys) = match pin.kind {
+            PinKind::Input => (None, "Up/Down: select"),
+            PinKind::Output => (None, "Up/Down: select  Enter: toggle"),
+            PinKind::Pwm => (
+                Some(Gauge::default().ratio(pin.duty).label(format!(
+                    "{:.0}% at {}Hz",
+                    pin.duty * 100.,
+                    pin.hz
+                ))),
+                "Up/Down: select  Enter: on/off  Left/Right: duty  +/-: frequency",
+            ),
+            PinKind::Servo => (
+                Some(
+                    Gauge::default()
+                        .ratio((pin.angle + 90.) / 180.)
+                        .label(format!("{:.0}°", pin.angle)),
+                ),
+                "Up/Down: select  Left/Right: angle",
+            ),
+        };
+        let inner = block.inner(layout[1]);
+        f.render_widget(block, layout[1]);
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(1), Constraint::Min(0)])
+            .split(inner);
+        if let Some(gauge) = gauge {
+            f.render_widget(gauge.use_unicode(true), rows[0]);
+        }
+        f.render_widget(
+            Paragraph::new(Span::styled(keys, dim)).wrap(Wrap { trim: true }),
+            rows[1],
+        );
     }
-    fn render_help(f: &mut Frame<impl Backend>) {
-        let layout = centered_rect(60, 40, f.size());
+    fn render_map(s: &State, _: &mut Areas, f: &mut Frame<impl Backend>, frame: Rect) {
+        let pose = s.pose;
+        let servo = match s.map {
+            Some(_) => s.sim_servo,
+            None => s.periph.servo,
+        };
+        let ultra = s.ultra.back().map(|(_, cm)| cm / 100.);
+
+        // fit the whole map, or a few meters around the robot
+        let points = match &s.map {
+            Some(map) => map
+                .walls
+                .iter()
+                .flatten()
+                .chain(&map.line)
+                .map(|p| (p[0], p[1]))
+                .chain([(pose.x, pose.y)])
+                .collect(),
+            None => vec![(pose.x - 1.5, pose.y - 1.5), (pose.x + 1.5, pose.y + 1.5)],
+        };
+        let block = Block::default().borders(Borders::ALL).title(format!(
+            "Map ({}) x {:.2}m y {:.2}m {:.0}°",
+            if s.map.is_some() {
+                "simulator"
+            } else {
+                "dead reckoning"
+            },
+            pose.x,
+            pose.y,
+            pose.heading.to_degrees(),
+        ));
+        let (x_bounds, y_bounds) = fit(&points, block.inner(frame));
+        let trail = s.trail.iter().copied().collect::<Vec<_>>();
+
+        let canvas = Canvas::default()
+            .block(block)
+            .marker(symbols::Marker::Braille)
+            .x_bounds(x_bounds)
+            .y_bounds(y_bounds)
+            .paint(|ctx| {
+                if let Some(map) = &s.map {
+                    for wall in &map.walls {
+                        draw_polyline(ctx, wall, Color::Gray);
+                    }
+                    draw_polyline(ctx, &map.line, Color::White);
+                }
+                ctx.draw(&Points {
+                    coords: &trail,
+                    color: Color::DarkGray,
+                });
+                ctx.layer();
+
+                if let Some(d) = ultra {
+                    let (x, y) = pose.transform(roland::ULTRA_MOUNT.0, roland::ULTRA_MOUNT.1);
+                    let a = pose.heading + servo.to_radians();
+                    ctx.draw(&canvas::Line {
+                        x1: x,
+                        y1: y,
+                        x2: x + d * a.cos(),
+                        y2: y + d * a.sin(),
+                        color: Color::Cyan,
+                    });
+                }
+                ctx.draw(&Circle {
+                    x: pose.x,
+                    y: pose.y,
+                    radius: roland::RADIUS,
+                    color: Color::Yellow,
+                });
+                let (x, y) = pose.transform(roland::RADIUS * 1.5, 0.);
+                ctx.draw(&canvas::Line {
+                    x1: pose.x,
+                    y1: pose.y,
+                    x2: x,
+                    y2: y,
+                    color: Color::Yellow,
+                });
+                ctx.layer();
+
+                // lit like on the Main tab
+                for (&(x, y), &on) in roland::TRACK_SENSORS.iter().zip(&s.track) {
+                    ctx.draw(&Points {
+                        coords: &[pose.transform(x, y)],
+                        color: if on { Color::Green } else { Color::DarkGray },
+                    });
+                }
+            });
+        f.render_widget(canvas, frame);
+    }
+    fn render_help(keys: &Keymap, replaying: bool, f: &mut Frame<impl Backend>) {
+        let layout = centered_rect(70, 90, f.size());
         f.render_widget(Clear, layout);
 
         let i = Style::default().add_modifier(Modifier::ITALIC);
         let b = Style::default().add_modifier(Modifier::BOLD);
-        let ctrls = [
-            ("WASD", "Drive robot"),
-            ("Up Arrow", "Increase drive speed"),
-            ("Down Arrow", "Decrease drive speed"),
+        let bound = Action::ALL
+            .into_iter()
+            .filter(|a| replaying || !a.is_replay())
+            .map(|a| (keys.keys(a).join(" / "), a.describe()))
+            .collect::<Vec<_>>();
+        // these belong to a tab and can't be rebound
+        let fixed = [
+            ("Ctrl-C", "Quit"),
+            ("P", "Pause the chart (Ultra sensor)"),
+            ("+ / -", "Move the threshold (Ultra sensor)"),
+            ("Up/Down", "Recall commands (Cmd Terminal)"),
+            ("Ctrl-R", "Search typed commands (Cmd Terminal)"),
+            (
+                "PgUp/PgDn",
+                "Select a command, Enter runs it again (Cmd Terminal)",
+            ),
+            ("Up/Down", "Select a pin (GPIO)"),
+            ("Enter", "Toggle an output (GPIO)"),
+            ("Left/Right", "Duty cycle or servo angle (GPIO)"),
+            ("+ / -", "PWM frequency (GPIO)"),
+            ("Home", "Reset the estimated position (Map)"),
+            ("Click", "Switch tabs, hold W/A/S/D, drag the speed"),
+            ("Scroll", "Select a command or zoom the chart"),
         ];
-        let text = ctrls
+        let text = bound
+            .iter()
+            .filter(|(k, _)| !k.is_empty())
+            .map(|(k, d)| (k.as_str(), *d))
+            .chain(fixed)
             .map(|c| {
                 Line::from(vec![
                     Span::styled(c.0.to_owned() + ": ", i),
                     Span::styled(c.1, b),
                 ])
             })
-            .to_vec();
+            .collect::<Vec<_>>();
         let p = Paragraph::new(text)
             .block(Block::default().title("Help").borders(Borders::ALL))
             .style(Style::default().fg(Color::White).bg(Color::Black))
@@ -410,7 +1792,7 @@ impl TUI {
 }
 impl Drop for TUI {
     fn drop(&mut self) {
-        restore_terminal(&mut self.term).unwrap();
+        self.restore().unwrap();
         eprintln!("TUI Dropped");
     }
 }
@@ -420,23 +1802,50 @@ impl Debug for TUI {
     }
 }
 
-fn setup_terminal() -> Result<Terminal<CrosstermBackend<Stdout>>> {
+fn setup_terminal(enhanced: bool) -> Result<Terminal<CrosstermBackend<Stdout>>> {
     let mut stdout = std::io::stdout();
     enable_raw_mode()?;
-    execute!(stdout, EnterAlternateScreen)?;
+    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
+    if enhanced {
+        // letters are only reported as keys, with their releases, when every key is an escape
+        // code, and then the alternate keys are needed to still type `:` and capitals
+        execute!(
+            stdout,
+            PushKeyboardEnhancementFlags(
+                KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES
+                    | KeyboardEnhancementFlags::REPORT_EVENT_TYPES
+                    | KeyboardEnhancementFlags::REPORT_ALL_KEYS_AS_ESCAPE_CODES
+                    | KeyboardEnhancementFlags::REPORT_ALTERNATE_KEYS
+            )
+        )?;
+    }
     Ok(Terminal::new(CrosstermBackend::new(stdout))?)
 }
 
-fn restore_terminal(terminal: &mut Terminal<CrosstermBackend<Stdout>>) -> Result<()> {
+fn restore_terminal(
+    terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+    enhanced: bool,
+) -> Result<()> {
+    if enhanced {
+        execute!(terminal.backend_mut(), PopKeyboardEnhancementFlags)?;
+    }
     disable_raw_mode()?;
-    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+    execute!(
+        terminal.backend_mut(),
+        LeaveAlternateScreen,
+        DisableMouseCapture
+    )?;
     Ok(terminal.show_cursor()?)
 }
 
-async fn event_listener(tx: Tx) -> Result<()> {
+async fn event_listener(tx: Tx, cancel: CancellationToken) -> Result<()> {
+    let _cancel = cancel.clone().drop_guard();
     let mut stream = EventStream::new();
     loop {
-        let e = stream.next().fuse().await;
+        let e = tokio::select! {
+            _ = cancel.cancelled() => return Ok(()),
+            e = stream.next().fuse() => e,
+        };
         match e {
             Some(Ok(ev)) => {
                 tx.send(Msg::Term(ev))?;
@@ -450,6 +1859,107 @@ async fn event_listener(tx: Tx) -> Result<()> {
     }
 }
 
+struct UltraStats {
+    min: f64,
+    max: f64,
+    mean: f64,
+    stddev: f64,
+    last: f64,
+}
+
+fn ultra_stats(data: &[(f64, f64)]) -> Option<UltraStats> {
+    let last = data.last()?.1;
+    let n = data.len() as f64;
+    let (min, max, sum) = data.iter().fold(
+        (f64::INFINITY, f64::NEG_INFINITY, 0.),
+        |(min, max, sum), (_, d)| (min.min(*d), max.max(*d), sum + d),
+    );
+    let mean = sum / n;
+    let var = data.iter().map(|(_, d)| (d - mean).powi(2)).sum::<f64>() / n;
+    Some(UltraStats {
+        min,
+        max,
+        mean,
+        stddev: var.sqrt(),
+        last,
+    })
+}
+
+/// Send a replayed entry to the UI as if it came from the robot
+fn play(tx: &Tx, entry: &Entry) {
+    let msg = match &entry.record {
+        Record::Track { sensors } => Msg::Roblib(ConcreteValue::TrackSensor(*sensors)),
+        Record::Ultra { distance } => Msg::Roblib(ConcreteValue::UltraSensor(*distance)),
+        Record::Gpio { pin, level } => Msg::Gpio(*pin, *level),
+        // drive commands move the robot on the Map tab
+        Record::Cmd { cmd } => match replay::parse_drive(cmd) {
+            Some((left, right)) => Msg::Wheels(left, right),
+            None => return,
+        },
+        Record::Event { .. } => return,
+    };
+    // the UI is the receiver, it's gone only when shutting down
+    let _ = tx.send(msg);
+}
+
+/// Canvas bounds that show all `points` at the same scale both ways, a terminal cell being about
+/// twice as tall as wide
+fn fit(points: &[(f64, f64)], area: Rect) -> ([f64; 2], [f64; 2]) {
+    let (mut x0, mut y0, mut x1, mut y1) = (f64::MAX, f64::MAX, f64::MIN, f64::MIN);
+    for &(x, y) in points {
+        (x0, y0, x1, y1) = (x0.min(x), y0.min(y), x1.max(x), y1.max(y));
+    }
+    let margin = 0.1;
+    let (cx, cy) = ((x0 + x1) / 2., (y0 + y1) / 2.);
+    let (mut w, mut h) = (x1 - x0 + 2. * margin, y1 - y0 + 2. * margin);
+    let aspect = area.width.max(1) as f64 / (area.height.max(1) as f64 * 2.);
+    if w / h > aspect {
+        h = w / aspect;
+    } else {
+        w = h * aspect;
+    }
+    ([cx - w / 2., cx + w / 2.], [cy - h / 2., cy + h / 2.])
+}
+
+fn draw_polyline(ctx: &mut canvas::Context, points: &[[f64; 2]], color: Color) {
+    for s in points.windows(2) {
+        ctx.draw(&canvas::Line {
+            x1: s[0][0],
+            y1: s[0][1],
+            x2: s[1][0],
+            y2: s[1][1],
+            color,
+        });
+    }
+}
+
+fn hit(r: Rect, x: u16, y: u16) -> bool {
+    x >= r.x && x < r.x + r.width && y >= r.y && y < r.y + r.height
+}
+
+/// The tab whose title is at `x`, each one is padded with a space and followed by a divider
+fn tab_at(tabs: Rect, x: u16) -> Option<usize> {
+    let mut start = tabs.x;
+    for (i, title) in TABS.iter().enumerate() {
+        let end = start + title.chars().count() as u16 + 2;
+        if x >= start && x <= end {
+            return Some(i);
+        }
+        start = end + 1;
+    }
+    None
+}
+
+/// The W, A, S or D at a point of the controls, they're 3 wide and centered
+fn drive_key_at(controls: Rect, x: u16, y: u16) -> Option<usize> {
+    if !hit(controls, x, y) {
+        return None;
+    }
+    let start = controls.x + controls.width.saturating_sub(12) / 2;
+    let i = x.checked_sub(start)? as usize / 3;
+    (i < 4).then_some(i)
+}
+
 fn speed_color(speed: f64) -> Color {
     let cols = [
         Color::LightGreen,
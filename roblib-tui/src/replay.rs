@@ -0,0 +1,155 @@
+use crate::{
+    dispatch,
+    record::{self, Entry, Record},
+};
+use anyhow::{bail, Result};
+use roblib_client::roblib::text_format;
+use std::{collections::HashSet, mem::discriminant, path::Path, time::Instant};
+
+/// seconds the seek keys jump
+const SEEK_STEP: f64 = 5.;
+
+/// A recording played back in place of a robot.
+///
+/// The UI calls [`tick`](Self::tick) regularly and sends on the entries that came due, so events
+/// arrive with their recorded spacing, to within a tick.
+#[derive(Debug)]
+pub struct Replay {
+    entries: Vec<Entry>,
+    /// index of the next entry to play
+    pos: usize,
+    /// seconds into the recording
+    clock: f64,
+    /// playback rate, 2 is twice as fast as recorded
+    pub speed: f64,
+    pub paused: bool,
+    last_tick: Instant,
+}
+
+impl Replay {
+    pub fn load(path: &Path, speed: f64) -> Result<Self> {
+        if speed <= 0. {
+            bail!("Replay speed must be positive");
+        }
+        let mut entries = record::load(path)?;
+        if entries.is_empty() {
+            bail!("{} has nothing to replay", path.display());
+        }
+        // times are since the recording TUI started, play from the first entry
+        let start = entries[0].at;
+        for e in &mut entries {
+            e.at -= start;
+        }
+
+        Ok(Self {
+            entries,
+            pos: 0,
+            clock: 0.,
+            speed,
+            paused: false,
+            last_tick: Instant::now(),
+        })
+    }
+
+    /// Advance the clock by the time since the last tick, returns the entries that came due
+    pub fn tick(&mut self) -> &[Entry] {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_tick).as_secs_f64();
+        self.last_tick = now;
+        if self.paused || self.finished() {
+            return &[];
+        }
+
+        self.clock = (self.clock + elapsed * self.speed).min(self.duration());
+        let start = self.pos;
+        while self
+            .entries
+            .get(self.pos)
+            .is_some_and(|e| e.at <= self.clock)
+        {
+            self.pos += 1;
+        }
+        &self.entries[start..self.pos]
+    }
+
+    /// Pause and play the next entry on its own
+    pub fn step(&mut self) -> Option<&Entry> {
+        self.paused = true;
+        let entry = self.entries.get(self.pos)?;
+        self.clock = entry.at;
+        self.pos += 1;
+        Some(entry)
+    }
+
+    /// Jump `steps` times [`SEEK_STEP`] back or forward. Entries in between are skipped, so this
+    /// returns the latest track, ultra and per pin GPIO readings at the new position, oldest
+    /// first
+    pub fn seek(&mut self, steps: f64) -> Vec<&Entry> {
+        self.clock = (self.clock + steps * SEEK_STEP).clamp(0., self.duration());
+        self.pos = self.entries.partition_point(|e| e.at <= self.clock);
+
+        let mut seen = HashSet::new();
+        let mut latest = vec![];
+        for e in self.entries[..self.pos].iter().rev() {
+            let pin = match &e.record {
+                Record::Track { .. } | Record::Ultra { .. } => 0,
+                Record::Gpio { pin, .. } => *pin,
+                _ => continue,
+            };
+            if seen.insert((discriminant(&e.record), pin)) {
+                latest.push(e);
+            }
+        }
+        latest.reverse();
+        latest
+    }
+
+    pub fn position(&self) -> f64 {
+        self.clock
+    }
+
+    pub fn duration(&self) -> f64 {
+        self.entries.last().map_or(0., |e| e.at)
+    }
+
+    pub fn finished(&self) -> bool {
+        self.pos == self.entries.len()
+    }
+
+    /// The wheel speeds of every drive command before the current position, with their times
+    pub fn drives(&self) -> Vec<(f64, (f64, f64))> {
+        self.entries[..self.pos]
+            .iter()
+            .filter_map(|e| match &e.record {
+                Record::Cmd { cmd } => Some((e.at, parse_drive(cmd)?)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The last `n` commands sent before the current position, oldest first
+    pub fn commands(&self, n: usize) -> Vec<(f64, &str)> {
+        let mut cmds = self.entries[..self.pos]
+            .iter()
+            .rev()
+            .filter_map(|e| match &e.record {
+                Record::Cmd { cmd } => Some((e.at, cmd.as_str())),
+                _ => None,
+            })
+            .take(n)
+            .collect::<Vec<_>>();
+        cmds.reverse();
+        cmds
+    }
+}
+
+/// The wheel speeds of a recorded drive command, a stop is both at 0
+pub fn parse_drive(cmd: &str) -> Option<(f64, f64)> {
+    let mut words = cmd.split_whitespace();
+    match words.next()? {
+        "stop" => Some((0., 0.)),
+        "drive" => Some((words.next()?.parse().ok()?, words.next()?.parse().ok()?)),
+        // typed in the Cmd Terminal
+        _ => dispatch::drives(&text_format::de::from_str(cmd).ok()?),
+    }
+}
@@ -0,0 +1,263 @@
+//! Batch scripts: roblib text format commands, one per line, with a few extras
+//!
+//! ```text
+//! # comments start with a hash
+//! let speed = 0.5
+//! repeat 4 {
+//!     m $speed $speed
+//!     sleep 1000
+//!     m 0 0
+//!     sleep 200
+//! }
+//! # where dead reckoning says the robot is, also in $x, $y and $heading
+//! pose
+//! ```
+
+use crate::{dispatch, render::Robot};
+use anyhow::{anyhow, bail, Context, Result};
+use drive::{Calibration, Odometry};
+use futures::{future::LocalBoxFuture, FutureExt};
+use roblib_client::roblib::{cmd::Concrete, roland::RolandAsync};
+use std::{collections::HashMap, time::Duration};
+
+#[derive(Debug, PartialEq)]
+enum Stmt {
+    Cmd(String),
+    Sleep(String),
+    Let(String, String),
+    Repeat(String, Vec<(usize, Stmt)>),
+    Pose,
+}
+
+/// A parsed script, statements are paired with their line numbers
+#[derive(Debug)]
+pub struct Script(Vec<(usize, Stmt)>);
+
+impl Script {
+    pub fn parse(src: &str) -> Result<Self> {
+        let mut lines = src
+            .lines()
+            .enumerate()
+            .map(|(i, l)| (i + 1, l.split('#').next().unwrap().trim()))
+            .filter(|(_, l)| !l.is_empty());
+
+        let body = parse_block(&mut lines, None)?;
+        Ok(Self(body))
+    }
+
+    /// Run against the robot, or just check every line when it's `None`.
+    ///
+    /// The robot is stopped if a line fails. The pose starts at the origin and is guessed from
+    /// the drive commands with `calibration`.
+    pub async fn run(&self, robot: Option<&Robot>, calibration: Calibration) -> Result<()> {
+        let mut exec = Exec {
+            robot,
+            vars: HashMap::new(),
+            odometry: Odometry::new(calibration),
+        };
+        let res = exec.block(&self.0).await;
+        if let (Err(_), Some(robot)) = (&res, robot) {
+            robot.stop().await?;
+        }
+        res
+    }
+}
+
+fn parse_block<'a>(
+    lines: &mut impl Iterator<Item = (usize, &'a str)>,
+    opened_at: Option<usize>,
+) -> Result<Vec<(usize, Stmt)>> {
+    let mut body = vec![];
+
+    while let Some((n, line)) = lines.next() {
+        if line == "}" {
+            if opened_at.is_none() {
+                bail!("line {n}: unexpected '}}'");
+            }
+            return Ok(body);
+        }
+
+        let (word, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let rest = rest.trim();
+        let stmt = match word {
+            "sleep" if !rest.is_empty() => Stmt::Sleep(rest.to_owned()),
+            "pose" if rest.is_empty() => Stmt::Pose,
+            "let" => {
+                let (name, value) = rest
+                    .split_once('=')
+                    .ok_or_else(|| anyhow!("line {n}: expected `let <name> = <value>`"))?;
+                Stmt::Let(name.trim().to_owned(), value.trim().to_owned())
+            }
+            "repeat" => {
+                let count = rest
+                    .strip_suffix('{')
+                    .ok_or_else(|| anyhow!("line {n}: expected `repeat <count> {{`"))?;
+                Stmt::Repeat(count.trim().to_owned(), parse_block(lines, Some(n))?)
+            }
+            _ => Stmt::Cmd(line.to_owned()),
+        };
+        body.push((n, stmt));
+    }
+
+    match opened_at {
+        Some(n) => bail!("line {n}: '{{' is never closed"),
+        None => Ok(body),
+    }
+}
+
+struct Exec<'r> {
+    robot: Option<&'r Robot>,
+    vars: HashMap<String, String>,
+    odometry: Odometry,
+}
+
+impl<'r> Exec<'r> {
+    fn block<'a>(&'a mut self, body: &'a [(usize, Stmt)]) -> LocalBoxFuture<'a, Result<()>> {
+        async move {
+            for (n, stmt) in body {
+                self.stmt(*n, stmt)
+                    .await
+                    .with_context(|| format!("line {n} failed"))?;
+            }
+            Ok(())
+        }
+        .boxed_local()
+    }
+
+    async fn stmt(&mut self, n: usize, stmt: &Stmt) -> Result<()> {
+        match stmt {
+            Stmt::Cmd(line) => {
+                let line = self.substitute(line)?;
+                let cmd: Concrete = roblib_client::roblib::text_format::de::from_str(&line)?;
+                // nothing would be there to show the events
+                if let Concrete::Subscribe(_) | Concrete::Unsubscribe(_) = cmd {
+                    bail!("subscriptions can't be used in scripts");
+                }
+                if let Some((left, right)) = dispatch::drives(&cmd) {
+                    self.odometry.drive(left, right);
+                }
+                let Some(robot) = self.robot else {
+                    println!("{n:>4}: {line}");
+                    return Ok(());
+                };
+                match dispatch::execute(cmd, &robot.transport).await? {
+                    Some(v) => println!("{n:>4}: {line} -> {}", dispatch::show(&v)),
+                    None => println!("{n:>4}: {line}"),
+                }
+            }
+            Stmt::Sleep(ms) => {
+                let ms: u64 = self.substitute(ms)?.parse()?;
+                self.odometry.advance(ms as f64 / 1000.);
+                if self.robot.is_some() {
+                    tokio::time::sleep(Duration::from_millis(ms)).await;
+                }
+            }
+            Stmt::Let(name, value) => {
+                let value = self.substitute(value)?;
+                self.vars.insert(name.clone(), value);
+            }
+            Stmt::Repeat(count, body) => {
+                let count: usize = self.substitute(count)?.parse()?;
+                // checking the body once is enough
+                let count = if self.robot.is_some() { count } else { 1 };
+                for _ in 0..count {
+                    self.block(body).await?;
+                }
+            }
+            Stmt::Pose => {
+                println!("{n:>4}: pose {}", dispatch::show_pose(self.odometry.pose()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Replace every `$name` with the variable's value, `$x`, `$y` and `$heading` are the pose
+    /// in meters and degrees unless the script sets them
+    fn substitute(&self, s: &str) -> Result<String> {
+        let mut out = String::with_capacity(s.len());
+        let mut rest = s;
+        while let Some(i) = rest.find('$') {
+            out.push_str(&rest[..i]);
+            let name_len = rest[i + 1..]
+                .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len() - i - 1);
+            let name = &rest[i + 1..i + 1 + name_len];
+            let pose = self.odometry.pose();
+            let value = match (self.vars.get(name), name) {
+                (Some(value), _) => value.clone(),
+                (None, "x") => format!("{:.3}", pose.x),
+                (None, "y") => format!("{:.3}", pose.y),
+                (None, "heading") => format!("{:.1}", pose.heading.to_degrees()),
+                (None, _) => bail!("undefined variable: ${name}"),
+            };
+            out.push_str(&value);
+            rest = &rest[i + 1 + name_len..];
+        }
+        out.push_str(rest);
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exec() -> Exec<'static> {
+        Exec {
+            robot: None,
+            vars: HashMap::new(),
+            odometry: Odometry::default(),
+        }
+    }
+
+    #[test]
+    fn nested_repeat() {
+        let src = "repeat 2 {\n  m 1 1 # go\n\n  repeat $n {\n    sleep 10\n  }\n}\npose";
+        let script = Script::parse(src).unwrap();
+        let inner = vec![(5, Stmt::Sleep("10".into()))];
+        let outer = vec![
+            (2, Stmt::Cmd("m 1 1".into())),
+            (4, Stmt::Repeat("$n".into(), inner)),
+        ];
+        assert_eq!(
+            script.0,
+            vec![(1, Stmt::Repeat("2".into(), outer)), (8, Stmt::Pose)]
+        );
+    }
+
+    #[test]
+    fn unbalanced_braces() {
+        let err = Script::parse("m 0 0\nrepeat 2 {\nm 1 1").unwrap_err();
+        assert_eq!(err.to_string(), "line 2: '{' is never closed");
+        let err = Script::parse("m 0 0\n}").unwrap_err();
+        assert_eq!(err.to_string(), "line 2: unexpected '}'");
+        assert!(Script::parse("repeat 2").is_err());
+        assert!(Script::parse("let x").is_err());
+    }
+
+    #[test]
+    fn substitute() {
+        let mut exec = exec();
+        exec.vars.insert("speed".into(), "0.5".into());
+        assert_eq!(exec.substitute("m $speed $speed").unwrap(), "m 0.5 0.5");
+        assert_eq!(
+            exec.substitute("$speed_").unwrap_err().to_string(),
+            "undefined variable: $speed_"
+        );
+        assert_eq!(exec.substitute("no vars").unwrap(), "no vars");
+    }
+
+    #[test]
+    fn pose_variables() {
+        let mut exec = exec();
+        exec.odometry.drive(1., 1.);
+        exec.odometry.advance(2.);
+        assert_eq!(
+            exec.substitute("$x $y $heading").unwrap(),
+            "1.000 0.000 0.0"
+        );
+        // the script's own variables win
+        exec.vars.insert("x".into(), "3".into());
+        assert_eq!(exec.substitute("$x").unwrap(), "3");
+    }
+}
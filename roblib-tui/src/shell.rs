@@ -0,0 +1,154 @@
+use crate::{config, dispatch, render::Robot, subs::Subs};
+use anyhow::Result;
+use drive::{Calibration, Odometry};
+use roblib_client::roblib::roland::RolandAsync;
+use rustyline::{
+    completion::{Completer, Pair},
+    error::ReadlineError,
+    hint::Hinter,
+    history::FileHistory,
+    Context, Editor, ExternalPrinter, Helper, Highlighter, Validator,
+};
+use std::{
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+const HELP: &str = "\
+Type roblib text format commands, their return values are printed.
+Tab completes command prefixes, the hint shows the arguments left and the command's name.
+
+  :subs         list active subscriptions
+  :unsub <id>   unsubscribe
+  :pose         where the robot should be, from the drive commands sent
+  help          show this
+  exit, Ctrl-D  quit";
+
+/// Line-oriented alternative to the TUI, for plain terminals and SSH sessions
+pub async fn run(robot: Robot, calibration: Calibration) -> Result<()> {
+    let mut ed = Editor::<ShellHelper, FileHistory>::new()?;
+    ed.set_helper(Some(ShellHelper));
+
+    let history = config::data_file("shell_history");
+    if let Some(path) = &history {
+        // there's no history on the first run
+        let _ = ed.load_history(path);
+    }
+
+    // subscription values get printed above the prompt
+    let printer = Mutex::new(ed.create_external_printer()?);
+    let mut subs = Subs::new(Arc::new(move |id, v| {
+        printer
+            .lock()
+            .unwrap()
+            .print(format!("#{id} {v:?}"))
+            .is_ok()
+    }));
+
+    let mut odometry = Odometry::new(calibration);
+    let mut reckoned = Instant::now();
+
+    println!("Type `help` for help");
+    loop {
+        // readline blocks, keep the runtime free for the robot connection
+        let (e, line) = tokio::task::spawn_blocking(move || {
+            let line = ed.readline("roblib> ");
+            (ed, line)
+        })
+        .await?;
+        ed = e;
+        // it kept driving while the prompt was up
+        odometry.advance(reckoned.elapsed().as_secs_f64());
+        reckoned = Instant::now();
+
+        let line = match line {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => Err(e)?,
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        ed.add_history_entry(line)?;
+
+        match line {
+            "exit" | "quit" => break,
+            "help" => println!("{HELP}"),
+            _ => match dispatch::eval(line, Some(&robot), &mut subs, &mut odometry).await {
+                Ok(Some(ret)) => println!("{ret}"),
+                Ok(None) => (),
+                Err(e) => println!("error: {e}"),
+            },
+        }
+    }
+
+    if let Some(path) = &history {
+        ed.save_history(path)?;
+    }
+    subs.unsubscribe_all(Some(&robot)).await?;
+    robot.stop().await?;
+    Ok(())
+}
+
+#[derive(Helper, Highlighter, Validator)]
+struct ShellHelper;
+
+impl Completer for ShellHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        // only the command itself, the arguments are free-form
+        let word = &line[..pos];
+        if word.contains(char::is_whitespace) {
+            return Ok((pos, vec![]));
+        }
+
+        let candidates = dispatch::COMMANDS
+            .iter()
+            .filter(|(name, prefix, _)| {
+                prefix.to_string().starts_with(word)
+                    || name.to_lowercase().starts_with(&word.to_lowercase())
+            })
+            .map(|(name, prefix, _)| Pair {
+                display: format!("{prefix}  {name}"),
+                replacement: format!("{prefix} "),
+            })
+            .collect();
+        Ok((0, candidates))
+    }
+}
+
+impl Hinter for ShellHelper {
+    type Hint = String;
+
+    fn hint(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> Option<String> {
+        if pos < line.len() {
+            return None;
+        }
+        let mut words = line.split_whitespace();
+        let prefix = words.next()?;
+        let (name, _, args) = dispatch::COMMANDS
+            .iter()
+            .find(|(_, p, _)| prefix.len() == p.len_utf8() && prefix.starts_with(*p))?;
+
+        // a word still being typed fills in its argument too
+        let rest: Vec<_> = args
+            .iter()
+            .skip(words.count())
+            .map(|a| format!("<{a}>"))
+            .collect();
+        let sep = if rest.is_empty() || line.ends_with(char::is_whitespace) {
+            ""
+        } else {
+            " "
+        };
+        Some(format!("{sep}{}  # {name}", rest.join(" ")))
+    }
+}
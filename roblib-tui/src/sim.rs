@@ -0,0 +1,54 @@
+use crate::render::{Msg, Tx};
+use anyhow::Result;
+use serde::Deserialize;
+use tokio::{
+    io::{AsyncBufReadExt, BufReader},
+    net::TcpStream,
+};
+use tokio_util::sync::CancellationToken;
+
+/// A line of roblib-sim's view feed
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SimView {
+    Map(SimMap),
+    Pose {
+        x: f64,
+        y: f64,
+        heading: f64,
+        servo: f64,
+    },
+}
+
+/// What the simulated robot drives on, in meters
+#[derive(Debug, Clone, Deserialize)]
+pub struct SimMap {
+    pub line: Vec<[f64; 2]>,
+    pub walls: Vec<Vec<[f64; 2]>>,
+}
+
+/// Follow roblib-sim's view feed at `addr`, sending [`Msg::Sim`]s until `cancel`, and
+/// [`Msg::SimEnded`] if the feed ends first
+pub fn spawn(addr: String, tx: Tx, cancel: CancellationToken) {
+    tokio::spawn(async move {
+        tokio::select! {
+            _ = cancel.cancelled() => (),
+            res = follow(&addr, &tx) => {
+                if let Err(e) = res {
+                    log::error!("Simulator view {addr} failed: {e}");
+                }
+                let _ = tx.send(Msg::SimEnded);
+            }
+        }
+    });
+}
+
+async fn follow(addr: &str, tx: &Tx) -> Result<()> {
+    let mut lines = BufReader::new(TcpStream::connect(addr).await?).lines();
+    while let Some(line) = lines.next_line().await? {
+        if tx.send(Msg::Sim(serde_json::from_str(&line)?)).is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
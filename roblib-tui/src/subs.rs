@@ -0,0 +1,153 @@
+use crate::render::Robot;
+use anyhow::{anyhow, Result};
+use roblib_client::roblib::event::{ConcreteType, ConcreteValue};
+use std::sync::Arc;
+use tokio::task::JoinHandle;
+
+/// Receives the values of a subscription, returning `false` ends it
+pub type Sink = Arc<dyn Fn(u32, ConcreteValue) -> bool + Send + Sync>;
+
+/// Subscriptions made by the user, each with an id to refer to it by.
+///
+/// The app's own subscriptions on the same client are [`reserve`](Self::reserve)d, so the
+/// user unsubscribing only stops forwarding them instead of dropping them on the robot.
+pub struct Subs {
+    next_id: u32,
+    active: Vec<Sub>,
+    reserved: Vec<ConcreteType>,
+    sink: Sink,
+}
+
+pub struct Sub {
+    pub id: u32,
+    pub event: ConcreteType,
+    task: Option<JoinHandle<()>>,
+}
+
+// subscribe to a concrete event and forward its values to the sink
+macro_rules! forward {
+    ($robot:expr, $ev:expr, $id:expr, $sink:expr, [$($variant:ident),* $(,)?]) => {
+        match $ev {
+            $(ConcreteType::$variant(e) => {
+                let mut rx = $robot.subscribe(e).await?;
+                let (id, sink) = ($id, $sink.clone());
+                tokio::spawn(async move {
+                    while let Ok(v) = rx.recv().await {
+                        if !sink(id, ConcreteValue::$variant(v)) {
+                            break;
+                        }
+                    }
+                })
+            })*
+        }
+    };
+}
+
+macro_rules! unsubscribe {
+    ($robot:expr, $ev:expr, [$($variant:ident),* $(,)?]) => {
+        match $ev {
+            $(ConcreteType::$variant(e) => $robot.unsubscribe(e).await?,)*
+        }
+    };
+}
+
+impl Subs {
+    pub fn new(sink: Sink) -> Self {
+        Self {
+            next_id: 1,
+            active: vec![],
+            reserved: vec![],
+            sink,
+        }
+    }
+
+    /// An event the app subscribes to itself, it's never unsubscribed from on the robot
+    pub fn reserve(&mut self, event: ConcreteType) {
+        self.reserved.push(event);
+    }
+
+    pub fn list(&self) -> &[Sub] {
+        &self.active
+    }
+
+    pub async fn subscribe(&mut self, robot: &Robot, event: ConcreteType) -> Result<u32> {
+        let id = self.next_id;
+        let task = self.forward(robot, id, event.clone()).await?;
+        self.next_id += 1;
+        self.active.push(Sub {
+            id,
+            event,
+            task: Some(task),
+        });
+        Ok(id)
+    }
+
+    pub async fn unsubscribe(&mut self, robot: Option<&Robot>, id: u32) -> Result<()> {
+        let i = self
+            .active
+            .iter()
+            .position(|s| s.id == id)
+            .ok_or_else(|| anyhow!("No subscription with id {id}"))?;
+        let mut sub = self.active.remove(i);
+        if let Some(task) = sub.task.take() {
+            task.abort();
+        }
+        // someone else still wants these events
+        let in_use = self
+            .reserved
+            .iter()
+            .chain(self.active.iter().map(|s| &s.event))
+            .any(|e| same_event(e, &sub.event));
+        if let (Some(robot), false) = (robot, in_use) {
+            unsubscribe!(robot, sub.event, [TrackSensor, UltraSensor, GpioPin]);
+        }
+        Ok(())
+    }
+
+    /// The id of the first subscription to the same event, with the same pin or interval
+    pub fn find(&self, event: &ConcreteType) -> Option<u32> {
+        self.active
+            .iter()
+            .find(|s| same_event(&s.event, event))
+            .map(|s| s.id)
+    }
+
+    pub async fn unsubscribe_all(&mut self, robot: Option<&Robot>) -> Result<()> {
+        while let Some(id) = self.active.first().map(|s| s.id) {
+            self.unsubscribe(robot, id).await?;
+        }
+        Ok(())
+    }
+
+    /// Subscribe again on a new connection, keeping the ids
+    pub async fn resubscribe(&mut self, robot: &Robot) -> Result<()> {
+        for i in 0..self.active.len() {
+            let (id, event) = (self.active[i].id, self.active[i].event.clone());
+            let task = self.forward(robot, id, event).await?;
+            if let Some(old) = self.active[i].task.replace(task) {
+                old.abort();
+            }
+        }
+        Ok(())
+    }
+
+    async fn forward(&self, robot: &Robot, id: u32, event: ConcreteType) -> Result<JoinHandle<()>> {
+        Ok(forward!(
+            robot,
+            event,
+            id,
+            self.sink,
+            [TrackSensor, UltraSensor, GpioPin]
+        ))
+    }
+}
+
+/// Whether two subscriptions are to the same events, the pin and the interval count too
+fn same_event(a: &ConcreteType, b: &ConcreteType) -> bool {
+    match (a, b) {
+        (ConcreteType::TrackSensor(_), ConcreteType::TrackSensor(_)) => true,
+        (ConcreteType::UltraSensor(a), ConcreteType::UltraSensor(b)) => a.0 == b.0,
+        (ConcreteType::GpioPin(a), ConcreteType::GpioPin(b)) => a.0 == b.0,
+        _ => false,
+    }
+}
@@ -0,0 +1,54 @@
+use crate::{
+    conn::Conn,
+    render::{Msg, Tx},
+};
+use anyhow::Result;
+use drive::watchdog::{Action, Watchdog, RESEND_INTERVAL};
+use roblib_client::{
+    roblib::{cmd, roland::RolandAsync},
+    transports::TransportAsync,
+};
+use std::sync::{Arc, Mutex};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio_util::sync::CancellationToken;
+
+/// Keep re-sending the current drive command, cancels `cancel` when the process is asked to quit.
+/// Sends [`Msg::WatchdogStop`] when it stops the robot, so the UI can record it.
+pub async fn run(
+    conn: Arc<Conn>,
+    watchdog: Arc<Mutex<Watchdog>>,
+    tx: Tx,
+    cancel: CancellationToken,
+) -> Result<()> {
+    let _cancel = cancel.clone().drop_guard();
+    let mut interval = tokio::time::interval(RESEND_INTERVAL);
+    let mut sigint = signal(SignalKind::interrupt())?;
+    let mut sigterm = signal(SignalKind::terminate())?;
+
+    loop {
+        tokio::select! {
+            _ = cancel.cancelled() => return Ok(()),
+            _ = interval.tick() => (),
+            _ = sigint.recv() => return Ok(()),
+            _ = sigterm.recv() => return Ok(()),
+        }
+
+        let action = watchdog.lock().unwrap().tick();
+        let Some(robot) = conn.robot() else {
+            continue;
+        };
+        let res = match action {
+            Action::Drive(left, right) => robot.drive(left, right).await,
+            Action::Nop => robot.transport.cmd(cmd::Nop).await,
+            Action::Stop => {
+                log::warn!("No input for too long, stopping the robot");
+                let res = robot.stop().await;
+                if res.is_ok() {
+                    let _ = tx.send(Msg::WatchdogStop);
+                }
+                res
+            }
+        };
+        conn.check(res);
+    }
+}